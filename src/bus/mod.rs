@@ -82,10 +82,14 @@ impl Bus {
     ///  bus.write(unsafe { std::mem::transmute::<&mut [u32], &mut [u8]>(&mut buffer) });
     ///  ```
     pub fn write(&self, write_buffer: &mut [u8]) {
-        unsafe {
-            // TODO: error handling. Not sure if an error here would be worth recovering from.
-            ioctl_write(self.regmap_fd, write_buffer).expect("error in IOCTL WRITE");
-        }
+        self.try_write(write_buffer).expect("error in IOCTL WRITE");
+    }
+
+    /// Fallible version of `write`. Returns an error if the MATRIX Kernel Modules reject the request.
+    pub fn try_write(&self, write_buffer: &mut [u8]) -> Result<(), Error> {
+        unsafe { ioctl_write(self.regmap_fd, write_buffer) }
+            .map_err(|_| Error::BusTransferFailed)?;
+        Ok(())
     }
 
     /// Send a read buffer to the MATRIX Kernel Modules. The buffer requires an `address` to request and
@@ -111,10 +115,13 @@ impl Bus {
     ///  println!("{:?}", buffer);
     ///  ```
     pub fn read(&self, read_buffer: &mut [u8]) {
-        unsafe {
-            // TODO: error handling. Not sure if an error here would be worth recovering from.
-            ioctl_read(self.regmap_fd, read_buffer).expect("error in IOCTL READ");
-        }
+        self.try_read(read_buffer).expect("error in IOCTL READ");
+    }
+
+    /// Fallible version of `read`. Returns an error if the MATRIX Kernel Modules reject the request.
+    pub fn try_read(&self, read_buffer: &mut [u8]) -> Result<(), Error> {
+        unsafe { ioctl_read(self.regmap_fd, read_buffer) }.map_err(|_| Error::BusTransferFailed)?;
+        Ok(())
     }
 
     /// Close the file descriptor that's communicating with the MATRIX Kernel's device file.
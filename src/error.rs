@@ -15,6 +15,28 @@ pub enum Error {
     PoisonedMutex,
     /// The GPIO pin selected does not exist
     InvalidGpioPin,
+    /// A read or write request to the MATRIX Kernel Modules failed.
+    BusTransferFailed,
+    /// The LEDs selected do not exist on this device.
+    InvalidLedRange,
+    /// No Everloop layer exists with the name given.
+    LayerNotFound,
+    /// An Everloop animation could not be loaded.
+    InvalidAnimation(String),
+    /// A color could not be parsed.
+    InvalidColor(String),
+    /// A sensor returned a value it cannot physically measure.
+    InvalidSensorReading(String),
+    /// A sensor's values have stopped updating.
+    StaleSensorData(String),
+    /// The feature requested is not available on this MATRIX device.
+    UnsupportedDevice,
+    /// The bus worker thread has stopped.
+    BusWorkerStopped,
+    /// A sensor calibration could not be computed or loaded.
+    InvalidCalibration(String),
+    /// The settings of an Everloop visualizer are out of range.
+    InvalidVisualizerConfig(String),
 }
 
 impl<'a> fmt::Display for Error {
@@ -24,6 +46,17 @@ impl<'a> fmt::Display for Error {
             Error::UnableToStartBus => write!(f, "Could not start the MATRIX bus."),
             Error::PoisonedMutex => write!(f, "A mutex lock was dropped during a panic."),
             Error::InvalidGpioPin => write!(f, "The GPIO pin selected does not exist. Valid pins are from 0-15"),
+            Error::BusTransferFailed => write!(f, "A read or write request to the MATRIX bus failed."),
+            Error::InvalidLedRange => write!(f, "The LEDs selected go past the number of LEDs on this device."),
+            Error::LayerNotFound => write!(f, "No Everloop layer exists with the name given."),
+            Error::InvalidAnimation(reason) => write!(f, "Invalid Everloop animation: {}", reason),
+            Error::InvalidColor(color) => write!(f, "\"{}\" is not a valid color.", color),
+            Error::InvalidSensorReading(reason) => write!(f, "Implausible sensor reading: {}", reason),
+            Error::StaleSensorData(reason) => write!(f, "Stale sensor data: {}", reason),
+            Error::UnsupportedDevice => write!(f, "This feature is not available on the current MATRIX device."),
+            Error::BusWorkerStopped => write!(f, "The bus worker thread has stopped."),
+            Error::InvalidCalibration(reason) => write!(f, "Invalid sensor calibration: {}", reason),
+            Error::InvalidVisualizerConfig(reason) => write!(f, "Invalid visualizer config: {}", reason),
             Error::KernelModulesNotInstalled => {
                 write!(f, "The MATRIX Kernel Modules have not been installed. In order to work, this library requires them!")
             }
@@ -0,0 +1,406 @@
+//! Frame based Everloop animations.
+//!
+//! With the `animation-files` feature, animations can be loaded from TOML or JSON files. Each frame either lists
+//! its LED colors or references an effect. Colors are written as `"#rrggbb"`, `"#rrggbbww"`, a CSS color name or a
+//! name from the file's palette.
+//!
+//! ```toml
+//! # Omit `loops` to repeat forever.
+//! loops = 3
+//!
+//! [palette]
+//! red = "#ff000000"
+//! warm = "#00000040"
+//!
+//! [[frames]]
+//! duration_ms = 100
+//! leds = ["red", "warm", "#00ff00"]
+//!
+//! [[frames]]
+//! duration_ms = 250
+//! effect = { type = "arc", start = 0.0, end = 90.0, color = "red" }
+//! ```
+//!
+//! The available effects are `fill { color }`, `point { angle, width, color }` and `arc { start, end, color }`.
+//!
+//! With the `images` feature, each column of a PNG/GIF strip can be imported as a frame, where row `n` maps to LED `n`.
+use super::{Everloop, Geometry, Rgbw};
+use crate::Error;
+use std::thread;
+use std::time::Duration;
+
+/// A single image shown on the Everloop.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// Color of each LED. LEDs not given are black.
+    pub leds: Vec<Rgbw>,
+    /// How long the frame is shown for.
+    pub duration: Duration,
+}
+
+/// A sequence of frames to play on the Everloop.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Animation {
+    pub frames: Vec<Frame>,
+    /// Number of times the animation is played. `None` repeats forever.
+    pub loops: Option<u32>,
+}
+
+impl Animation {
+    /// Shorthand way to create an Animation instance.
+    pub fn new(frames: Vec<Frame>, loops: Option<u32>) -> Animation {
+        Animation { frames, loops }
+    }
+
+    /// Make sure the animation can be played on a device with the given layout.
+    ///
+    /// # Example
+    /// ```
+    /// use matrix_rhal::everloop::animation::{Animation, Frame};
+    /// use matrix_rhal::everloop::{Direction, Geometry};
+    /// use std::time::Duration;
+    ///
+    /// let geometry = Geometry::new(18, 0.0, Direction::Clockwise);
+    /// let frame = Frame { leds: vec![], duration: Duration::from_millis(0) };
+    ///
+    /// // nothing to wait for between frames
+    /// assert!(Animation::new(vec![frame.clone()], None).validate(&geometry).is_err());
+    ///
+    /// let frame = Frame { duration: Duration::from_millis(100), ..frame };
+    /// assert!(Animation::new(vec![frame], None).validate(&geometry).is_ok());
+    /// ```
+    pub fn validate(&self, geometry: &Geometry) -> Result<(), Error> {
+        if self.frames.is_empty() {
+            return Err(Error::InvalidAnimation("no frames were given".to_string()));
+        }
+
+        for (index, frame) in self.frames.iter().enumerate() {
+            if frame.leds.len() > geometry.leds {
+                return Err(Error::InvalidAnimation(format!(
+                    "frame {} has {} LEDs, but this device only has {}",
+                    index,
+                    frame.leds.len(),
+                    geometry.leds
+                )));
+            }
+        }
+
+        // playing would rewrite the LEDs in a tight loop
+        if self.cycle_duration() == Duration::from_secs(0) {
+            return Err(Error::InvalidAnimation("every frame lasts 0ms".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Time it takes to play every frame once.
+    pub fn cycle_duration(&self) -> Duration {
+        self.frames.iter().map(|frame| frame.duration).sum()
+    }
+
+    /// Return the frame shown `elapsed` time after the animation started. `None` is returned once it has finished.
+    pub fn frame_at(&self, elapsed: Duration) -> Option<&Frame> {
+        let cycle = self.cycle_duration();
+        if cycle == Duration::from_secs(0) {
+            return None;
+        }
+
+        let cycles_done = elapsed.as_nanos() / cycle.as_nanos();
+        if let Some(loops) = self.loops {
+            if cycles_done >= loops as u128 {
+                return None;
+            }
+        }
+
+        // find the frame within the current cycle
+        let mut remaining = elapsed.as_nanos() % cycle.as_nanos();
+        for frame in &self.frames {
+            if remaining < frame.duration.as_nanos() {
+                return Some(frame);
+            }
+            remaining -= frame.duration.as_nanos();
+        }
+
+        None
+    }
+
+    /// Play the animation on the Everloop. This blocks until the animation finishes, which is never if it loops forever.
+    pub fn play(&self, everloop: &Everloop) -> Result<(), Error> {
+        self.validate(&everloop.geometry())?;
+
+        let mut played = 0;
+        while !matches!(self.loops, Some(loops) if played >= loops) {
+            for frame in &self.frames {
+                everloop.try_set(&frame.leds)?;
+                thread::sleep(frame.duration);
+            }
+            played += 1;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "animation-files")]
+mod file {
+    use super::{Animation, Frame};
+    use crate::everloop::{Geometry, Rgbw};
+    use crate::Error;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct AnimationFile {
+        #[serde(default)]
+        palette: HashMap<String, String>,
+        loops: Option<u32>,
+        frames: Vec<FrameFile>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct FrameFile {
+        duration_ms: u64,
+        leds: Option<Vec<String>>,
+        effect: Option<Effect>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+    enum Effect {
+        Fill {
+            color: String,
+        },
+        Point {
+            angle: f32,
+            width: f32,
+            color: String,
+        },
+        Arc {
+            start: f32,
+            end: f32,
+            color: String,
+        },
+    }
+
+    impl Animation {
+        /// Load an animation written in TOML.
+        ///
+        /// # Example
+        /// ```
+        /// use matrix_rhal::everloop::{animation::Animation, Direction, Geometry};
+        ///
+        /// let geometry = Geometry::new(18, 0.0, Direction::CounterClockwise);
+        /// let animation = Animation::from_toml(
+        ///     r##"
+        ///     [palette]
+        ///     red = "#ff0000"
+        ///
+        ///     [[frames]]
+        ///     duration_ms = 100
+        ///     leds = ["red", "#00ff0000"]
+        ///
+        ///     [[frames]]
+        ///     duration_ms = 100
+        ///     effect = { type = "fill", color = "red" }
+        ///     "##,
+        ///     &geometry,
+        /// )
+        /// .unwrap();
+        ///
+        /// assert_eq!(animation.frames.len(), 2);
+        /// assert_eq!(animation.frames[1].leds.len(), 18);
+        /// ```
+        pub fn from_toml(source: &str, geometry: &Geometry) -> Result<Animation, Error> {
+            let file = toml::from_str(source).map_err(|error| invalid(error.message()))?;
+            resolve(file, geometry)
+        }
+
+        /// Load an animation written in JSON.
+        pub fn from_json(source: &str, geometry: &Geometry) -> Result<Animation, Error> {
+            let file = serde_json::from_str(source).map_err(|error| invalid(&error.to_string()))?;
+            resolve(file, geometry)
+        }
+
+        /// Load an animation file. Files ending in `.json` are read as JSON, everything else as TOML.
+        pub fn load<P: AsRef<Path>>(path: P, geometry: &Geometry) -> Result<Animation, Error> {
+            let path = path.as_ref();
+            let source =
+                std::fs::read_to_string(path).map_err(|error| Error::Any(Box::new(error)))?;
+
+            match path.extension().and_then(|extension| extension.to_str()) {
+                Some("json") => Animation::from_json(&source, geometry),
+                _ => Animation::from_toml(&source, geometry),
+            }
+        }
+    }
+
+    /// Turn a parsed file into frames, resolving colors and effects for the given layout.
+    fn resolve(file: AnimationFile, geometry: &Geometry) -> Result<Animation, Error> {
+        let color = |name: &str| -> Result<Rgbw, Error> {
+            let value = file.palette.get(name).map_or(name, |value| value.as_str());
+            value
+                .parse::<Rgbw>()
+                .map_err(|_| invalid(&format!("unknown color \"{}\"", name)))
+        };
+
+        let mut frames = Vec::with_capacity(file.frames.len());
+        for (index, frame) in file.frames.iter().enumerate() {
+            let leds = match (&frame.leds, &frame.effect) {
+                (Some(leds), None) => leds
+                    .iter()
+                    .map(|led| color(led))
+                    .collect::<Result<_, _>>()?,
+                (None, Some(Effect::Fill { color: name })) => vec![color(name)?; geometry.leds],
+                (
+                    None,
+                    Some(Effect::Point {
+                        angle,
+                        width,
+                        color: name,
+                    }),
+                ) => geometry.light_at_angle(*angle, *width, color(name)?),
+                (
+                    None,
+                    Some(Effect::Arc {
+                        start,
+                        end,
+                        color: name,
+                    }),
+                ) => geometry.arc(*start, *end, color(name)?),
+                _ => {
+                    return Err(invalid(&format!(
+                        "frame {} must have either `leds` or `effect`",
+                        index
+                    )))
+                }
+            };
+
+            frames.push(Frame {
+                leds,
+                duration: Duration::from_millis(frame.duration_ms),
+            });
+        }
+
+        let animation = Animation::new(frames, file.loops);
+        animation.validate(geometry)?;
+        Ok(animation)
+    }
+
+    fn invalid(message: &str) -> Error {
+        Error::InvalidAnimation(message.to_string())
+    }
+}
+
+#[cfg(feature = "images")]
+mod image {
+    use super::{Animation, Frame};
+    use crate::everloop::{Geometry, Rgbw};
+    use crate::Error;
+    use std::io::Read;
+    use std::time::Duration;
+
+    impl Animation {
+        /// Import a PNG strip, where each column is a frame and row `n` maps to LED `n`.
+        ///
+        /// The image must be exactly as tall as the number of LEDs on the device.
+        pub fn from_png<R: Read>(
+            reader: R,
+            geometry: &Geometry,
+            frame_duration: Duration,
+        ) -> Result<Animation, Error> {
+            let mut decoder = png::Decoder::new(reader);
+            decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+            let mut reader = decoder.read_info().map_err(invalid)?;
+            let mut buffer = vec![0; reader.output_buffer_size()];
+            let info = reader.next_frame(&mut buffer).map_err(invalid)?;
+
+            let samples = info.color_type.samples();
+            let pixels = buffer[..info.buffer_size()]
+                .chunks(samples)
+                .map(|pixel| match samples {
+                    // grayscale images
+                    1 | 2 => Rgbw::new(pixel[0], pixel[0], pixel[0], 0),
+                    _ => Rgbw::new(pixel[0], pixel[1], pixel[2], 0),
+                })
+                .collect::<Vec<_>>();
+
+            from_strip(
+                &pixels,
+                info.width as usize,
+                info.height as usize,
+                geometry,
+                frame_duration,
+            )
+        }
+
+        /// Import the first frame of a GIF strip, where each column is a frame and row `n` maps to LED `n`.
+        ///
+        /// The image must be exactly as tall as the number of LEDs on the device.
+        pub fn from_gif<R: Read>(
+            reader: R,
+            geometry: &Geometry,
+            frame_duration: Duration,
+        ) -> Result<Animation, Error> {
+            let mut options = gif::DecodeOptions::new();
+            options.set_color_output(gif::ColorOutput::RGBA);
+
+            let mut decoder = options.read_info(reader).map_err(invalid)?;
+            let frame = decoder
+                .read_next_frame()
+                .map_err(invalid)?
+                .ok_or_else(|| Error::InvalidAnimation("the GIF has no frames".to_string()))?;
+
+            let pixels = frame
+                .buffer
+                .chunks(4)
+                .map(|pixel| Rgbw::new(pixel[0], pixel[1], pixel[2], 0))
+                .collect::<Vec<_>>();
+
+            from_strip(
+                &pixels,
+                frame.width as usize,
+                frame.height as usize,
+                geometry,
+                frame_duration,
+            )
+        }
+    }
+
+    /// Turn each column of an image into a frame.
+    fn from_strip(
+        pixels: &[Rgbw],
+        width: usize,
+        height: usize,
+        geometry: &Geometry,
+        frame_duration: Duration,
+    ) -> Result<Animation, Error> {
+        if height != geometry.leds {
+            return Err(Error::InvalidAnimation(format!(
+                "the image is {} pixels tall, but this device has {} LEDs",
+                height, geometry.leds
+            )));
+        }
+
+        let frames = (0..width)
+            .map(|column| Frame {
+                leds: (0..height)
+                    .map(|row| pixels[row * width + column])
+                    .collect(),
+                duration: frame_duration,
+            })
+            .collect();
+
+        let animation = Animation::new(frames, None);
+        animation.validate(geometry)?;
+        Ok(animation)
+    }
+
+    fn invalid<E: std::fmt::Display>(error: E) -> Error {
+        Error::InvalidAnimation(error.to_string())
+    }
+}
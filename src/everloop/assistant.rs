@@ -0,0 +1,283 @@
+use super::{Everloop, Geometry, Rgbw};
+use crate::Error;
+use std::f32::consts::PI;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The states a voice assistant moves through.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AssistantState {
+    /// Waiting for the wake word.
+    Idle,
+    /// The wake word was just heard.
+    WakeWord,
+    /// Recording a request. `direction` is the angle (degrees) of the speaker, if known.
+    Listening { direction: Option<f32> },
+    /// Processing a request.
+    Thinking,
+    /// Playing back a response.
+    Speaking,
+    /// The microphones are turned off.
+    Muted,
+    /// Something went wrong.
+    Error,
+}
+
+/// How the LEDs move while a state is active.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Animation {
+    /// Every LED is off.
+    Off,
+    /// Every LED shows the same color.
+    Solid,
+    /// Every LED fades in and out once per `period`.
+    Pulse { period: Duration },
+    /// A `width` degree wide light travels around the ring once per `period`.
+    Spin { period: Duration, width: f32 },
+    /// A `width` degree wide light points toward the speaker. Falls back to `Solid` if the direction is unknown.
+    Beam { width: f32 },
+}
+
+/// The color and animation used for a state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Look {
+    pub color: Rgbw,
+    pub animation: Animation,
+}
+
+impl Look {
+    /// Shorthand way to create a Look instance.
+    pub fn new(color: Rgbw, animation: Animation) -> Look {
+        Look { color, animation }
+    }
+
+    /// Build the frame for this look, `elapsed` time after it started.
+    pub fn frame(
+        &self,
+        geometry: &Geometry,
+        elapsed: Duration,
+        direction: Option<f32>,
+    ) -> Vec<Rgbw> {
+        let solid = |color: Rgbw| vec![color; geometry.leds];
+
+        match self.animation {
+            Animation::Off => solid(Rgbw::black()),
+            Animation::Solid => solid(self.color),
+            Animation::Pulse { period } => {
+                let phase = cycle(elapsed, period);
+                solid(self.color.scale((1.0 - (2.0 * PI * phase).cos()) / 2.0))
+            }
+            Animation::Spin { period, width } => {
+                let angle = 360.0 * cycle(elapsed, period);
+                geometry.light_at_angle(angle, width, self.color)
+            }
+            Animation::Beam { width } => match direction {
+                Some(direction) => geometry.light_at_angle(direction, width, self.color),
+                None => solid(self.color),
+            },
+        }
+    }
+}
+
+/// The look of every assistant state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    pub idle: Look,
+    pub wake_word: Look,
+    pub listening: Look,
+    pub thinking: Look,
+    pub speaking: Look,
+    pub muted: Look,
+    pub error: Look,
+}
+
+impl Theme {
+    /// Return the look used for a state.
+    pub fn look(&self, state: AssistantState) -> Look {
+        match state {
+            AssistantState::Idle => self.idle,
+            AssistantState::WakeWord => self.wake_word,
+            AssistantState::Listening { .. } => self.listening,
+            AssistantState::Thinking => self.thinking,
+            AssistantState::Speaking => self.speaking,
+            AssistantState::Muted => self.muted,
+            AssistantState::Error => self.error,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        let blue = Rgbw::new(0, 80, 255, 0);
+        let cyan = Rgbw::new(0, 200, 255, 0);
+
+        Theme {
+            idle: Look::new(Rgbw::black(), Animation::Off),
+            wake_word: Look::new(cyan, Animation::Solid),
+            listening: Look::new(blue, Animation::Beam { width: 60.0 }),
+            thinking: Look::new(
+                cyan,
+                Animation::Spin {
+                    period: Duration::from_millis(1000),
+                    width: 60.0,
+                },
+            ),
+            speaking: Look::new(
+                blue,
+                Animation::Pulse {
+                    period: Duration::from_millis(1200),
+                },
+            ),
+            muted: Look::new(Rgbw::new(120, 0, 0, 0), Animation::Solid),
+            error: Look::new(
+                Rgbw::new(255, 40, 0, 0),
+                Animation::Pulse {
+                    period: Duration::from_millis(500),
+                },
+            ),
+        }
+    }
+}
+
+/// A state and the moment it became active.
+#[derive(Debug, Clone, Copy)]
+struct ActiveState {
+    state: AssistantState,
+    since: Instant,
+}
+
+/// Drives the Everloop through the states of a voice assistant.
+///
+/// Feed application events into `set_state` and call `update` from a render loop (30-60 times per second).
+/// Changing states crossfades from the previous look over the configured transition time.
+///
+/// # Example
+/// ```no_run
+/// use matrix_rhal::everloop::assistant::{AssistantRing, AssistantState};
+///
+/// # let bus = matrix_rhal::Bus::init().unwrap();
+/// let everloop = matrix_rhal::Everloop::new(&bus);
+/// let ring = AssistantRing::new(&everloop);
+///
+/// ring.set_state(AssistantState::Listening { direction: Some(45.0) }).unwrap();
+/// loop {
+///     ring.update().unwrap();
+///     std::thread::sleep(std::time::Duration::from_millis(33));
+/// }
+/// ```
+#[derive(Debug)]
+pub struct AssistantRing<'a> {
+    everloop: &'a Everloop<'a>,
+    theme: Theme,
+    transition: Duration,
+    current: Mutex<ActiveState>,
+    previous: Mutex<Option<ActiveState>>,
+}
+
+impl<'a> AssistantRing<'a> {
+    /// Return an instance of AssistantRing, starting in the `Idle` state with the default theme.
+    pub fn new(everloop: &'a Everloop<'a>) -> AssistantRing<'a> {
+        AssistantRing {
+            everloop,
+            theme: Theme::default(),
+            transition: Duration::from_millis(300),
+            current: Mutex::new(ActiveState {
+                state: AssistantState::Idle,
+                since: Instant::now(),
+            }),
+            previous: Mutex::new(None),
+        }
+    }
+
+    /// Change the look of each state.
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    /// Change how long it takes to fade from one state to the next.
+    pub fn set_transition(&mut self, transition: Duration) {
+        self.transition = transition;
+    }
+
+    /// Return the current state.
+    pub fn state(&self) -> Result<AssistantState, Error> {
+        Ok(self.current.lock()?.state)
+    }
+
+    /// Move to a new state. Updating the speaker's direction while listening does not restart the transition.
+    pub fn set_state(&self, state: AssistantState) -> Result<(), Error> {
+        let current = &mut *self.current.lock()?;
+
+        if std::mem::discriminant(&current.state) == std::mem::discriminant(&state) {
+            current.state = state;
+            return Ok(());
+        }
+
+        *self.previous.lock()? = Some(*current);
+        *current = ActiveState {
+            state,
+            since: Instant::now(),
+        };
+
+        Ok(())
+    }
+
+    /// Build the frame that should be shown at `now`.
+    pub fn frame(&self, now: Instant) -> Result<Vec<Rgbw>, Error> {
+        let geometry = self.everloop.geometry();
+        let current = *self.current.lock()?;
+        let previous = &mut *self.previous.lock()?;
+
+        let target = self.render_state(&geometry, current, now);
+
+        // crossfade from the previous state
+        let progress = if self.transition.as_secs_f32() > 0.0 {
+            now.saturating_duration_since(current.since).as_secs_f32()
+                / self.transition.as_secs_f32()
+        } else {
+            1.0
+        };
+
+        match previous {
+            Some(from) if progress < 1.0 => {
+                let from = self.render_state(&geometry, *from, now);
+                Ok(from
+                    .iter()
+                    .zip(target)
+                    .map(|(from, to)| from.lerp(to, progress))
+                    .collect())
+            }
+            _ => {
+                *previous = None;
+                Ok(target)
+            }
+        }
+    }
+
+    /// Render the current frame to the Everloop.
+    pub fn update(&self) -> Result<(), Error> {
+        self.everloop.try_set(&self.frame(Instant::now())?)
+    }
+
+    fn render_state(&self, geometry: &Geometry, active: ActiveState, now: Instant) -> Vec<Rgbw> {
+        let direction = match active.state {
+            AssistantState::Listening { direction } => direction,
+            _ => None,
+        };
+
+        self.theme.look(active.state).frame(
+            geometry,
+            now.saturating_duration_since(active.since),
+            direction,
+        )
+    }
+}
+
+/// Return how far into the current cycle of `period` we are, from `0.0` to `1.0`.
+fn cycle(elapsed: Duration, period: Duration) -> f32 {
+    if period.as_secs_f32() == 0.0 {
+        return 0.0;
+    }
+
+    (elapsed.as_secs_f32() / period.as_secs_f32()).fract()
+}
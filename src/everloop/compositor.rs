@@ -0,0 +1,304 @@
+use super::{Everloop, Rgbw};
+use crate::Error;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// How a layer's colors are combined with the layers below it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlendMode {
+    /// Cover the colors below.
+    Over,
+    /// Add each channel together, saturating at 255.
+    Add,
+    /// Multiply each channel together, where 255 acts as 1.0.
+    Multiply,
+    /// Keep the brightest value of each channel.
+    Max,
+}
+
+impl BlendMode {
+    /// Combine a color with the one below it.
+    pub fn blend(self, below: Rgbw, above: Rgbw) -> Rgbw {
+        let channels = |f: fn(u8, u8) -> u8| {
+            Rgbw::new(
+                f(below.r, above.r),
+                f(below.g, above.g),
+                f(below.b, above.b),
+                f(below.w, above.w),
+            )
+        };
+
+        match self {
+            BlendMode::Over => above,
+            BlendMode::Add => channels(u8::saturating_add),
+            BlendMode::Multiply => channels(|a, b| ((a as u16 * b as u16) / 255) as u8),
+            BlendMode::Max => channels(std::cmp::max),
+        }
+    }
+}
+
+/// A single named layer of LEDs. LEDs set to `None` are transparent.
+#[derive(Debug, Clone)]
+struct Layer {
+    name: String,
+    z_index: i32,
+    opacity: f32,
+    blend_mode: BlendMode,
+    leds: Vec<Option<Rgbw>>,
+    expires_at: Option<Instant>,
+}
+
+impl Layer {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|expires_at| now >= expires_at)
+    }
+}
+
+/// Lets multiple parts of an application share the Everloop through stacked layers.
+///
+/// Layers with a higher `z_index` are drawn on top. Layers sharing a `z_index` are drawn in the order they were added.
+///
+/// # Example
+/// ```no_run
+/// use matrix_rhal::everloop::compositor::{BlendMode, Compositor};
+/// use matrix_rhal::Rgbw;
+/// use std::time::Duration;
+///
+/// # let bus = matrix_rhal::Bus::init().unwrap();
+/// let everloop = matrix_rhal::Everloop::new(&bus);
+/// let compositor = Compositor::new(&everloop);
+///
+/// compositor.add_layer("ambient", 0, BlendMode::Over).unwrap();
+/// compositor.add_layer("notification", 10, BlendMode::Add).unwrap();
+///
+/// compositor.set_layer("ambient", &vec![Rgbw::new(0, 0, 30, 0); 35]).unwrap();
+/// compositor.set_layer("notification", &[Rgbw::new(255, 0, 0, 0); 4]).unwrap();
+/// compositor.set_timeout("notification", Duration::from_secs(3)).unwrap();
+///
+/// compositor.render().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Compositor<'a> {
+    everloop: &'a Everloop<'a>,
+    layers: Mutex<Vec<Layer>>,
+}
+
+impl<'a> Compositor<'a> {
+    /// Return an instance of Compositor with no layers.
+    pub fn new(everloop: &'a Everloop<'a>) -> Compositor<'a> {
+        Compositor {
+            everloop,
+            layers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Add a fully transparent layer. If the layer already exists, only its `z_index` and `blend_mode` are updated.
+    ///
+    /// A layer whose timeout has passed no longer exists, so adding it again starts over without a timeout.
+    ///
+    /// # Example
+    /// ```
+    /// use matrix_rhal::everloop::compositor::{BlendMode, Compositor};
+    /// use matrix_rhal::Rgbw;
+    /// use std::time::Duration;
+    ///
+    /// # let bus = matrix_rhal::Bus {
+    /// #     device_file: "/dev/null",
+    /// #     regmap_fd: -1,
+    /// #     device_name: matrix_rhal::Device::Creator,
+    /// #     device_version: 0,
+    /// #     device_leds: 35,
+    /// #     fpga_frequency: 0,
+    /// # };
+    /// let everloop = matrix_rhal::Everloop::new(&bus);
+    /// let compositor = Compositor::new(&everloop);
+    ///
+    /// compositor.add_layer("notification", 0, BlendMode::Over).unwrap();
+    /// compositor.set_timeout("notification", Duration::from_millis(10)).unwrap();
+    /// std::thread::sleep(Duration::from_millis(20));
+    ///
+    /// // the expired layer is gone, even before the next `compose`
+    /// assert!(compositor.set_layer("notification", &[Rgbw::new(0, 255, 0, 0)]).is_err());
+    ///
+    /// compositor.add_layer("notification", 0, BlendMode::Over).unwrap();
+    /// compositor.set_layer("notification", &[Rgbw::new(0, 255, 0, 0)]).unwrap();
+    /// assert_eq!(compositor.compose().unwrap()[0], Rgbw::new(0, 255, 0, 0));
+    /// ```
+    pub fn add_layer(&self, name: &str, z_index: i32, blend_mode: BlendMode) -> Result<(), Error> {
+        let layers = &mut *self.live_layers()?;
+
+        let layer = match layers.iter().position(|layer| layer.name == name) {
+            Some(index) => {
+                let mut layer = layers.remove(index);
+                layer.z_index = z_index;
+                layer.blend_mode = blend_mode;
+                layer
+            }
+            None => Layer {
+                name: name.to_string(),
+                z_index,
+                opacity: 1.0,
+                blend_mode,
+                leds: vec![None; self.everloop.geometry().leds],
+                expires_at: None,
+            },
+        };
+
+        // keep layers sorted from bottom to top
+        let index = layers
+            .iter()
+            .position(|other| other.z_index > z_index)
+            .unwrap_or(layers.len());
+        layers.insert(index, layer);
+
+        Ok(())
+    }
+
+    /// Remove a layer.
+    pub fn remove_layer(&self, name: &str) -> Result<(), Error> {
+        let layers = &mut *self.live_layers()?;
+        let index = layers
+            .iter()
+            .position(|layer| layer.name == name)
+            .ok_or(Error::LayerNotFound)?;
+
+        layers.remove(index);
+        Ok(())
+    }
+
+    /// Map each `Rgbw` to the respective LED of a layer. LEDs not given become transparent.
+    pub fn set_layer(&self, name: &str, leds: &[Rgbw]) -> Result<(), Error> {
+        let leds: Vec<Option<Rgbw>> = leds.iter().copied().map(Some).collect();
+        self.set_layer_mask(name, &leds)
+    }
+
+    /// Map each LED of a layer, where `None` is transparent. LEDs not given become transparent.
+    pub fn set_layer_mask(&self, name: &str, leds: &[Option<Rgbw>]) -> Result<(), Error> {
+        let device_leds = self.everloop.geometry().leds;
+        if leds.len() > device_leds {
+            return Err(Error::InvalidLedRange);
+        }
+
+        self.with_layer(name, |layer| {
+            layer.leds = leds.to_vec();
+            layer.leds.resize(device_leds, None);
+        })
+    }
+
+    /// Make every LED of a layer transparent.
+    pub fn clear_layer(&self, name: &str) -> Result<(), Error> {
+        self.with_layer(name, |layer| {
+            layer.leds.iter_mut().for_each(|led| *led = None);
+        })
+    }
+
+    /// Set how visible a layer is, from `0.0` (invisible) to `1.0` (fully visible). NaN and infinite values hide
+    /// the layer.
+    ///
+    /// # Example
+    /// ```
+    /// use matrix_rhal::everloop::compositor::{BlendMode, Compositor};
+    /// use matrix_rhal::Rgbw;
+    ///
+    /// # let bus = matrix_rhal::Bus {
+    /// #     device_file: "/dev/null",
+    /// #     regmap_fd: -1,
+    /// #     device_name: matrix_rhal::Device::Creator,
+    /// #     device_version: 0,
+    /// #     device_leds: 35,
+    /// #     fpga_frequency: 0,
+    /// # };
+    /// let everloop = matrix_rhal::Everloop::new(&bus);
+    /// let compositor = Compositor::new(&everloop);
+    ///
+    /// compositor.add_layer("base", 0, BlendMode::Over).unwrap();
+    /// compositor.set_layer("base", &[Rgbw::new(0, 0, 255, 0)]).unwrap();
+    /// compositor.add_layer("glitch", 1, BlendMode::Over).unwrap();
+    /// compositor.set_layer("glitch", &[Rgbw::new(255, 0, 0, 0)]).unwrap();
+    ///
+    /// compositor.set_opacity("glitch", f32::NAN).unwrap();
+    /// assert_eq!(compositor.compose().unwrap()[0], Rgbw::new(0, 0, 255, 0));
+    /// ```
+    pub fn set_opacity(&self, name: &str, opacity: f32) -> Result<(), Error> {
+        let opacity = if opacity.is_finite() {
+            opacity.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        self.with_layer(name, |layer| layer.opacity = opacity)
+    }
+
+    /// Change how a layer is combined with the layers below it.
+    pub fn set_blend_mode(&self, name: &str, blend_mode: BlendMode) -> Result<(), Error> {
+        self.with_layer(name, |layer| layer.blend_mode = blend_mode)
+    }
+
+    /// Remove a layer automatically once `timeout` has passed.
+    pub fn set_timeout(&self, name: &str, timeout: Duration) -> Result<(), Error> {
+        let expires_at = Instant::now() + timeout;
+        self.with_layer(name, |layer| layer.expires_at = Some(expires_at))
+    }
+
+    /// Keep a layer until it's removed manually. This undoes `set_timeout`.
+    pub fn clear_timeout(&self, name: &str) -> Result<(), Error> {
+        self.with_layer(name, |layer| layer.expires_at = None)
+    }
+
+    /// Return the names of every layer, from bottom to top. Expired layers are not included.
+    pub fn layers(&self) -> Result<Vec<String>, Error> {
+        let now = Instant::now();
+        let layers = self.layers.lock()?;
+
+        Ok(layers
+            .iter()
+            .filter(|layer| !layer.is_expired(now))
+            .map(|layer| layer.name.clone())
+            .collect())
+    }
+
+    /// Flatten every layer into a single frame. Expired layers are removed.
+    pub fn compose(&self) -> Result<Vec<Rgbw>, Error> {
+        let layers = &*self.live_layers()?;
+
+        let mut frame = vec![Rgbw::black(); self.everloop.geometry().leds];
+        for layer in layers.iter() {
+            for (below, above) in frame.iter_mut().zip(&layer.leds) {
+                if let Some(above) = above {
+                    let blended = layer.blend_mode.blend(*below, *above);
+                    *below = below.lerp(blended, layer.opacity);
+                }
+            }
+        }
+
+        Ok(frame)
+    }
+
+    /// Compose every layer and send the result to the Everloop.
+    pub fn render(&self) -> Result<(), Error> {
+        self.everloop.try_set(&self.compose()?)
+    }
+
+    /// Shortener to modify a single layer by name.
+    fn with_layer<F>(&self, name: &str, modify: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Layer),
+    {
+        let layers = &mut *self.live_layers()?;
+        let layer = layers
+            .iter_mut()
+            .find(|layer| layer.name == name)
+            .ok_or(Error::LayerNotFound)?;
+
+        modify(layer);
+        Ok(())
+    }
+
+    /// Lock the layers after removing the expired ones.
+    fn live_layers(&self) -> Result<MutexGuard<'_, Vec<Layer>>, Error> {
+        let now = Instant::now();
+        let mut layers = self.layers.lock()?;
+        layers.retain(|layer| !layer.is_expired(now));
+        Ok(layers)
+    }
+}
@@ -0,0 +1,251 @@
+//! Ways of showing information on the Everloop for devices without a screen.
+//!
+//! Text can be blinked in Morse code, the time can be shown as a clock face and numbers can be shown as a count of
+//! lit LEDs, a gauge or binary digits.
+use super::compositor::BlendMode;
+use super::{Everloop, Geometry, Rgbw};
+use crate::Error;
+use std::thread;
+use std::time::Duration;
+
+/// Return the Morse code of a character, as dots (`.`) and dashes (`-`).
+pub fn morse_code(character: char) -> Option<&'static str> {
+    let code = match character.to_ascii_uppercase() {
+        'A' => ".-",
+        'B' => "-...",
+        'C' => "-.-.",
+        'D' => "-..",
+        'E' => ".",
+        'F' => "..-.",
+        'G' => "--.",
+        'H' => "....",
+        'I' => "..",
+        'J' => ".---",
+        'K' => "-.-",
+        'L' => ".-..",
+        'M' => "--",
+        'N' => "-.",
+        'O' => "---",
+        'P' => ".--.",
+        'Q' => "--.-",
+        'R' => ".-.",
+        'S' => "...",
+        'T' => "-",
+        'U' => "..-",
+        'V' => "...-",
+        'W' => ".--",
+        'X' => "-..-",
+        'Y' => "-.--",
+        'Z' => "--..",
+        '0' => "-----",
+        '1' => ".----",
+        '2' => "..---",
+        '3' => "...--",
+        '4' => "....-",
+        '5' => ".....",
+        '6' => "-....",
+        '7' => "--...",
+        '8' => "---..",
+        '9' => "----.",
+        '.' => ".-.-.-",
+        ',' => "--..--",
+        '?' => "..--..",
+        '/' => "-..-.",
+        '-' => "-....-",
+        ':' => "---...",
+        '=' => "-...-",
+        _ => return None,
+    };
+
+    Some(code)
+}
+
+/// Turn text into a sequence of on/off periods using standard Morse timing.
+///
+/// A dot lasts one `unit`, a dash three. Parts of a character are separated by one unit, characters by three and
+/// words by seven. Characters without a Morse code are skipped.
+///
+/// # Example
+/// ```
+/// use matrix_rhal::everloop::display::morse_timeline;
+/// use std::time::Duration;
+///
+/// let unit = Duration::from_millis(100);
+/// let timeline = morse_timeline("ET", unit);
+///
+/// // dot, character gap, dash
+/// assert_eq!(timeline, vec![(true, unit), (false, unit * 3), (true, unit * 3)]);
+/// ```
+pub fn morse_timeline(text: &str, unit: Duration) -> Vec<(bool, Duration)> {
+    let mut timeline: Vec<(bool, Duration)> = Vec::new();
+    // units of silence owed before the next symbol
+    let mut pending_gap = 0;
+
+    for character in text.chars() {
+        if character.is_whitespace() {
+            // a word gap only matters between two characters
+            if !timeline.is_empty() {
+                pending_gap = 7;
+            }
+            continue;
+        }
+
+        let code = match morse_code(character) {
+            Some(code) => code,
+            None => continue,
+        };
+
+        for (index, symbol) in code.chars().enumerate() {
+            let gap = if index > 0 { 1 } else { pending_gap };
+            if gap > 0 {
+                timeline.push((false, unit * gap));
+            }
+
+            let length = if symbol == '-' { 3 } else { 1 };
+            timeline.push((true, unit * length));
+        }
+
+        pending_gap = 3;
+    }
+
+    timeline
+}
+
+/// Blink text in Morse code on every LED. This blocks until the whole message has been shown.
+pub fn play_morse(
+    everloop: &Everloop,
+    text: &str,
+    unit: Duration,
+    color: Rgbw,
+) -> Result<(), Error> {
+    for (on, duration) in morse_timeline(text, unit) {
+        everloop.try_set_all(if on { color } else { Rgbw::black() })?;
+        thread::sleep(duration);
+    }
+
+    everloop.try_set_all(Rgbw::black())
+}
+
+/// Colors used to draw a clock face.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockFace {
+    pub hour: Rgbw,
+    pub minute: Rgbw,
+    pub second: Rgbw,
+    /// Color of the LEDs closest to each hour mark. `None` leaves them off.
+    pub ticks: Option<Rgbw>,
+}
+
+impl Default for ClockFace {
+    fn default() -> Self {
+        ClockFace {
+            hour: Rgbw::new(255, 0, 0, 0),
+            minute: Rgbw::new(0, 255, 0, 0),
+            second: Rgbw::new(0, 0, 255, 0),
+            ticks: Some(Rgbw::new(0, 0, 0, 10)),
+        }
+    }
+}
+
+impl ClockFace {
+    /// Build a frame showing the time. 12 o'clock points along the board's Y axis and hands move clockwise.
+    ///
+    /// Hands that overlap have their colors added together.
+    pub fn frame(&self, geometry: &Geometry, hour: u32, minute: u32, second: u32) -> Vec<Rgbw> {
+        // angle of a hand that has gone `fraction` of the way around the clock
+        let hand = |fraction: f32| 90.0 - 360.0 * fraction;
+
+        let mut frame = vec![Rgbw::black(); geometry.leds];
+        if let Some(tick) = self.ticks {
+            for mark in 0..12 {
+                frame[geometry.nearest_led(hand(mark as f32 / 12.0))] = tick;
+            }
+        }
+
+        let hours = (hour % 12) as f32 + minute as f32 / 60.0;
+        let minutes = minute as f32 + second as f32 / 60.0;
+        let hands = [
+            (hand(hours / 12.0), self.hour),
+            (hand(minutes / 60.0), self.minute),
+            (hand(second as f32 / 60.0), self.second),
+        ];
+
+        for (angle, color) in hands.iter() {
+            let layer = geometry.light_at_angle(*angle, 0.0, *color);
+            for (led, above) in frame.iter_mut().zip(layer) {
+                *led = BlendMode::Add.blend(*led, above);
+            }
+        }
+
+        frame
+    }
+}
+
+/// Build a frame where `value` LEDs are lit, starting from LED 0. Handy for blink-free error codes.
+pub fn count(geometry: &Geometry, value: usize, color: Rgbw) -> Result<Vec<Rgbw>, Error> {
+    if value > geometry.leds {
+        return Err(Error::InvalidLedRange);
+    }
+
+    let mut frame = vec![color; value];
+    frame.resize(geometry.leds, Rgbw::black());
+    Ok(frame)
+}
+
+/// Build a frame where the portion of the ring lit matches `value / max`. The last LED is partially lit.
+pub fn gauge(geometry: &Geometry, value: f32, max: f32, color: Rgbw) -> Vec<Rgbw> {
+    let fraction = if max > 0.0 {
+        (value / max).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let lit = fraction * geometry.leds as f32;
+
+    (0..geometry.leds)
+        .map(|led| color.scale(lit - led as f32))
+        .collect()
+}
+
+/// Build a frame showing the lowest `bits` binary digits of `value`, most significant bit first, starting at LED 0.
+///
+/// The ring is split into one group of LEDs per bit. Groups of more than one LED keep their last LED off so that
+/// neighboring bits can be told apart.
+///
+/// # Example
+/// ```
+/// use matrix_rhal::everloop::{display, Direction, Geometry, Rgbw};
+///
+/// // show the IP octet 192 on a MATRIX Voice
+/// let geometry = Geometry::new(18, 0.0, Direction::CounterClockwise);
+/// let frame = display::binary(&geometry, 192, 8, Rgbw::white(), Rgbw::new(0, 0, 20, 0)).unwrap();
+///
+/// // 18 LEDs / 8 bits = 2 LEDs per bit, where the first LED of each group shows the bit
+/// assert_eq!(frame[0], Rgbw::white());
+/// assert_eq!(frame[2], Rgbw::white());
+/// assert_eq!(frame[4], Rgbw::new(0, 0, 20, 0));
+/// ```
+pub fn binary(
+    geometry: &Geometry,
+    value: u32,
+    bits: usize,
+    one: Rgbw,
+    zero: Rgbw,
+) -> Result<Vec<Rgbw>, Error> {
+    if bits == 0 || bits > geometry.leds || bits > 32 {
+        return Err(Error::InvalidLedRange);
+    }
+
+    let group = geometry.leds / bits;
+    let mut frame = vec![Rgbw::black(); geometry.leds];
+
+    for bit in 0..bits {
+        let is_set = value >> (bits - 1 - bit) & 1 == 1;
+        let lit = if group > 1 { group - 1 } else { group };
+
+        for led in frame.iter_mut().skip(bit * group).take(lit) {
+            *led = if is_set { one } else { zero };
+        }
+    }
+
+    Ok(frame)
+}
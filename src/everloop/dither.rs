@@ -0,0 +1,215 @@
+//! High precision colors with temporal dithering.
+//!
+//! With only 8 bits per channel, slow fades near black visibly step. Colors here are kept at 16 bits per channel
+//! and, on every rendered frame, each LED alternates between the two closest 8-bit values so that the average over
+//! time matches the 16-bit color. For the effect to be smooth, frames must be rendered continuously (60Hz or more)
+//! even when the colors are not changing.
+use super::{Everloop, Rgbw};
+use crate::Error;
+use std::sync::Mutex;
+
+/// Colors that represent a single LED, with 16 bits per channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rgbw16 {
+    pub r: u16,
+    pub g: u16,
+    pub b: u16,
+    pub w: u16,
+}
+
+impl Rgbw16 {
+    /// Shorthand way to create an Rgbw16 instance.
+    pub fn new(r: u16, g: u16, b: u16, w: u16) -> Rgbw16 {
+        Rgbw16 { r, g, b, w }
+    }
+
+    /// Scale every channel by `factor`. Values are clamped to `0.0..=1.0`.
+    pub fn scale(self, factor: f32) -> Rgbw16 {
+        let factor = factor.clamp(0.0, 1.0);
+        let channel = |value: u16| (value as f32 * factor).round() as u16;
+
+        Rgbw16::new(
+            channel(self.r),
+            channel(self.g),
+            channel(self.b),
+            channel(self.w),
+        )
+    }
+
+    /// Linearly interpolate towards `other`. A `t` of `0.0` returns `self` and `1.0` returns `other`.
+    pub fn lerp(self, other: Rgbw16, t: f32) -> Rgbw16 {
+        let t = t.clamp(0.0, 1.0);
+        let channel = |a: u16, b: u16| (a as f32 + (b as f32 - a as f32) * t).round() as u16;
+
+        Rgbw16::new(
+            channel(self.r, other.r),
+            channel(self.g, other.g),
+            channel(self.b, other.b),
+            channel(self.w, other.w),
+        )
+    }
+
+    fn channels(self) -> [u16; 4] {
+        [self.r, self.g, self.b, self.w]
+    }
+}
+
+/// Expands each channel so that `255` becomes `65535`.
+impl From<Rgbw> for Rgbw16 {
+    fn from(color: Rgbw) -> Self {
+        let channel = |value: u8| value as u16 * 257;
+        Rgbw16::new(
+            channel(color.r),
+            channel(color.g),
+            channel(color.b),
+            channel(color.w),
+        )
+    }
+}
+
+/// Rounds each channel to the closest 8-bit value.
+impl From<Rgbw16> for Rgbw {
+    fn from(color: Rgbw16) -> Self {
+        let channel = |value: u16| ((value as u32 + 128) / 257) as u8;
+        Rgbw::new(
+            channel(color.r),
+            channel(color.g),
+            channel(color.b),
+            channel(color.w),
+        )
+    }
+}
+
+/// Turns 16-bit frames into 8-bit frames, carrying each LED's rounding error over to the next frame.
+#[derive(Debug, Clone, Default)]
+pub struct Ditherer {
+    /// Rounding error of each LED channel, in 8-bit units.
+    error: Vec<[f32; 4]>,
+}
+
+impl Ditherer {
+    /// Return an instance of Ditherer with no accumulated error.
+    pub fn new() -> Ditherer {
+        Ditherer::default()
+    }
+
+    /// Produce the next 8-bit frame to display.
+    ///
+    /// # Example
+    /// ```
+    /// use matrix_rhal::everloop::dither::{Ditherer, Rgbw16};
+    ///
+    /// // roughly half of the dimmest 8-bit red
+    /// let frame = [Rgbw16::new(128, 0, 0, 0)];
+    ///
+    /// let mut ditherer = Ditherer::new();
+    /// let lit_frames = (0..100).filter(|_| ditherer.next_frame(&frame)[0].r == 1).count();
+    /// assert!((49..=51).contains(&lit_frames));
+    /// ```
+    pub fn next_frame(&mut self, frame: &[Rgbw16]) -> Vec<Rgbw> {
+        self.error.resize(frame.len(), [0.0; 4]);
+
+        frame
+            .iter()
+            .zip(self.error.iter_mut())
+            .map(|(led, error)| {
+                let mut output = [0u8; 4];
+                for (channel, value) in led.channels().iter().enumerate() {
+                    let target = *value as f32 / 257.0 + error[channel];
+                    let shown = target.round().clamp(0.0, 255.0);
+
+                    error[channel] = target - shown;
+                    output[channel] = shown as u8;
+                }
+
+                Rgbw::new(output[0], output[1], output[2], output[3])
+            })
+            .collect()
+    }
+
+    /// Forget any accumulated error.
+    pub fn reset(&mut self) {
+        self.error.clear();
+    }
+}
+
+/// Renders 16-bit frames to the Everloop through a `Ditherer`.
+///
+/// # Example
+/// ```no_run
+/// use matrix_rhal::everloop::dither::{DitheredEverloop, Rgbw16};
+/// use std::time::Duration;
+///
+/// # let bus = matrix_rhal::Bus::init().unwrap();
+/// let everloop = matrix_rhal::Everloop::new(&bus);
+/// let dithered = DitheredEverloop::new(&everloop);
+///
+/// // slowly breathe a dim white
+/// let dim = Rgbw16::new(0, 0, 0, 600);
+/// for step in 0..600 {
+///     let brightness = (step as f32 / 600.0 * std::f32::consts::PI).sin();
+///     dithered.set_all(dim.scale(brightness)).unwrap();
+///     std::thread::sleep(Duration::from_millis(16));
+/// }
+/// ```
+#[derive(Debug)]
+pub struct DitheredEverloop<'a> {
+    everloop: &'a Everloop<'a>,
+    ditherer: Mutex<Ditherer>,
+    frame: Mutex<Vec<Rgbw16>>,
+    brightness: Mutex<f32>,
+}
+
+impl<'a> DitheredEverloop<'a> {
+    /// Return an instance of DitheredEverloop with every LED off.
+    pub fn new(everloop: &'a Everloop<'a>) -> DitheredEverloop<'a> {
+        DitheredEverloop {
+            everloop,
+            ditherer: Mutex::new(Ditherer::new()),
+            frame: Mutex::new(vec![Rgbw16::default(); everloop.geometry().leds]),
+            brightness: Mutex::new(1.0),
+        }
+    }
+
+    /// Replace the current frame and render it. LEDs not set are defaulted to black.
+    pub fn set(&self, leds: &[Rgbw16]) -> Result<(), Error> {
+        let device_leds = self.everloop.geometry().leds;
+        if leds.len() > device_leds {
+            return Err(Error::InvalidLedRange);
+        }
+
+        {
+            let frame = &mut *self.frame.lock()?;
+            frame.clear();
+            frame.extend_from_slice(leds);
+            frame.resize(device_leds, Rgbw16::default());
+        }
+
+        self.render()
+    }
+
+    /// Set all LEDs to a single color and render it.
+    pub fn set_all(&self, color: Rgbw16) -> Result<(), Error> {
+        self.set(&vec![color; self.everloop.geometry().leds])
+    }
+
+    /// Scale every LED by `brightness` (`0.0..=1.0`) before dithering. Useful for a smooth night mode.
+    pub fn set_brightness(&self, brightness: f32) -> Result<(), Error> {
+        *self.brightness.lock()? = brightness.clamp(0.0, 1.0);
+        Ok(())
+    }
+
+    /// Render the next dithered version of the current frame. Call this on every tick of the render loop.
+    pub fn render(&self) -> Result<(), Error> {
+        let brightness = *self.brightness.lock()?;
+        let frame: Vec<Rgbw16> = self
+            .frame
+            .lock()?
+            .iter()
+            .map(|led| led.scale(brightness))
+            .collect();
+
+        let leds = self.ditherer.lock()?.next_frame(&frame);
+        self.everloop.try_set(&leds)
+    }
+}
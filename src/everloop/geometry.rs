@@ -0,0 +1,197 @@
+use super::Rgbw;
+use crate::bus::memory_map::*;
+use crate::Device;
+
+/// The direction LED indices travel around the ring, as seen from the top of the board.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Clockwise,
+    CounterClockwise,
+}
+
+/// Physical layout of the LEDs on a MATRIX device.
+///
+/// Angles are in degrees, measured counter-clockwise from the board's X axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geometry {
+    /// Number of LEDs in the ring.
+    pub leds: usize,
+    /// Angle of LED 0 relative to the board's X axis.
+    pub offset: f32,
+    /// Direction LED indices increase in.
+    pub direction: Direction,
+}
+
+impl Geometry {
+    /// Create a custom LED layout.
+    pub fn new(leds: usize, offset: f32, direction: Direction) -> Geometry {
+        Geometry {
+            leds,
+            offset,
+            direction,
+        }
+    }
+
+    /// Return the LED layout of a MATRIX device. `None` is returned for an unknown device.
+    ///
+    /// On both boards LED 0 sits at 170° and indices go clockwise, matching the `led_angles_mcreator` and
+    /// `led_angles_mvoice` tables of MATRIX's ODAS demo (matrix-io/odas, `demo/matrix-demos/matrix_odas.cpp`),
+    /// which use the same axes as the microphone array.
+    ///
+    /// # Example
+    /// ```
+    /// use matrix_rhal::everloop::geometry::Geometry;
+    /// use matrix_rhal::Device;
+    ///
+    /// let creator = Geometry::for_device(&Device::Creator).unwrap();
+    /// assert_eq!(creator.nearest_led(170.0), 0);
+    /// assert_eq!(creator.nearest_led(5.0), 16);
+    /// assert_eq!(creator.nearest_led(180.0), 34);
+    ///
+    /// let voice = Geometry::for_device(&Device::Voice).unwrap();
+    /// assert_eq!(voice.led_angle(4), 90.0);
+    /// assert_eq!(voice.nearest_led(350.0), 9);
+    /// ```
+    pub fn for_device(device: &Device) -> Option<Geometry> {
+        match device {
+            Device::Creator => Some(Geometry::new(
+                device_info::MATRIX_CREATOR_LEDS as usize,
+                170.0,
+                Direction::Clockwise,
+            )),
+            Device::Voice => Some(Geometry::new(
+                device_info::MATRIX_VOICE_LEDS as usize,
+                170.0,
+                Direction::Clockwise,
+            )),
+            _ => None,
+        }
+    }
+
+    /// Degrees between two neighboring LEDs.
+    pub fn spacing(&self) -> f32 {
+        360.0 / self.leds as f32
+    }
+
+    /// Return the angle an LED points to.
+    ///
+    /// # Example
+    /// ```
+    /// use matrix_rhal::everloop::geometry::{Direction, Geometry};
+    ///
+    /// let geometry = Geometry::new(18, 0.0, Direction::Clockwise);
+    /// assert_eq!(geometry.led_angle(1), 340.0);
+    /// ```
+    pub fn led_angle(&self, index: usize) -> f32 {
+        normalize(self.offset + self.sign() * index as f32 * self.spacing())
+    }
+
+    /// Return the fractional LED index that points to `angle`.
+    ///
+    /// A position of `2.5` means the angle falls halfway between LED 2 and LED 3.
+    ///
+    /// # Example
+    /// ```
+    /// use matrix_rhal::everloop::geometry::{Direction, Geometry};
+    ///
+    /// let geometry = Geometry::new(18, 10.0, Direction::Clockwise);
+    /// assert_eq!(geometry.position(10.0), 0.0);
+    /// assert_eq!(geometry.position(0.0), 0.5);
+    /// // past LED 17, the position wraps back towards LED 0
+    /// assert_eq!(geometry.position(20.0), 17.5);
+    /// ```
+    pub fn position(&self, angle: f32) -> f32 {
+        let steps = normalize(self.sign() * (angle - self.offset)) / self.spacing();
+        steps % self.leds as f32
+    }
+
+    /// Return the LED closest to `angle`.
+    ///
+    /// # Example
+    /// ```
+    /// use matrix_rhal::everloop::geometry::{Direction, Geometry};
+    ///
+    /// let geometry = Geometry::new(18, 0.0, Direction::CounterClockwise);
+    /// assert_eq!(geometry.nearest_led(41.0), 2);
+    /// assert_eq!(geometry.nearest_led(-12.0), 17);
+    /// // closer to LED 0 than to LED 17
+    /// assert_eq!(geometry.nearest_led(355.0), 0);
+    /// ```
+    pub fn nearest_led(&self, angle: f32) -> usize {
+        self.position(angle).round() as usize % self.leds
+    }
+
+    /// Build a frame where LEDs within `width` degrees centered on `angle` are lit with `color`.
+    ///
+    /// LEDs just outside of the lit region fade out over one LED spacing, so an angle that falls between
+    /// two LEDs is shared across both of them. A `width` of `0.0` produces a single point of light.
+    pub fn light_at_angle(&self, angle: f32, width: f32, color: Rgbw) -> Vec<Rgbw> {
+        let half_width = width.max(0.0) / 2.0;
+        let spacing = self.spacing();
+
+        (0..self.leds)
+            .map(|led| {
+                let distance = angle_distance(self.led_angle(led), angle);
+                let intensity = if distance <= half_width {
+                    1.0
+                } else {
+                    1.0 - (distance - half_width) / spacing
+                };
+
+                color.scale(intensity)
+            })
+            .collect()
+    }
+
+    /// Build a frame with an arc of `color` sweeping counter-clockwise from `start` to `end`.
+    ///
+    /// # Example
+    /// ```
+    /// use matrix_rhal::everloop::geometry::{Direction, Geometry};
+    /// use matrix_rhal::Rgbw;
+    ///
+    /// let geometry = Geometry::new(36, 0.0, Direction::CounterClockwise);
+    /// let red = Rgbw::new(255, 0, 0, 0);
+    ///
+    /// // the arc wraps through 0 degrees instead of going the long way around
+    /// let frame = geometry.arc(350.0, 10.0, red);
+    /// assert_eq!(frame[35], red);
+    /// assert_eq!(frame[0], red);
+    /// assert_eq!(frame[1], red);
+    /// assert_eq!(frame[18], Rgbw::black());
+    /// ```
+    pub fn arc(&self, start: f32, end: f32, color: Rgbw) -> Vec<Rgbw> {
+        let mut sweep = normalize(end - start);
+        if sweep == 0.0 && end != start {
+            sweep = 360.0;
+        }
+
+        self.light_at_angle(start + sweep / 2.0, sweep, color)
+    }
+
+    fn sign(&self) -> f32 {
+        match self.direction {
+            Direction::Clockwise => -1.0,
+            Direction::CounterClockwise => 1.0,
+        }
+    }
+}
+
+/// Wrap an angle into `0.0..360.0`.
+pub fn normalize(angle: f32) -> f32 {
+    let angle = angle % 360.0;
+    let angle = if angle < 0.0 { angle + 360.0 } else { angle };
+
+    // a tiny negative angle can round up to a full turn
+    if angle >= 360.0 {
+        0.0
+    } else {
+        angle
+    }
+}
+
+/// Shortest distance, in degrees, between two angles.
+pub fn angle_distance(a: f32, b: f32) -> f32 {
+    let difference = normalize(a - b);
+    difference.min(360.0 - difference)
+}
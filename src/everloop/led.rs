@@ -1,5 +1,11 @@
+use super::css_colors::CSS_COLORS;
+use crate::Error;
+use std::fmt;
+use std::str::FromStr;
+
 /// Colors that represent a single LED.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
 pub struct Rgbw {
     pub r: u8,
     pub g: u8,
@@ -23,7 +29,142 @@ impl Rgbw {
         Self::new(255, 255, 255, 255)
     }
 
+    /// Scale every channel by `factor`. Values are clamped to `0.0..=1.0`.
+    pub fn scale(self, factor: f32) -> Rgbw {
+        let factor = factor.clamp(0.0, 1.0);
+        let channel = |value: u8| (value as f32 * factor).round() as u8;
+
+        Rgbw::new(
+            channel(self.r),
+            channel(self.g),
+            channel(self.b),
+            channel(self.w),
+        )
+    }
+
+    /// Linearly interpolate towards `other`. A `t` of `0.0` returns `self` and `1.0` returns `other`.
+    pub fn lerp(self, other: Rgbw, t: f32) -> Rgbw {
+        let t = t.clamp(0.0, 1.0);
+        let channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+
+        Rgbw::new(
+            channel(self.r, other.r),
+            channel(self.g, other.g),
+            channel(self.b, other.b),
+            channel(self.w, other.w),
+        )
+    }
+
+    /// Pack the LED the way the Everloop expects it in memory: one byte per channel, in `r, g, b, w` order.
     pub fn as_bytes(self) -> i32 {
-        unsafe { std::mem::transmute::<Rgbw, i32>(self) }
+        i32::from_ne_bytes([self.r, self.g, self.b, self.w])
+    }
+
+    /// Inverse of `as_bytes`. Decodes a single LED from the Everloop's memory.
+    pub fn from_bytes(bytes: i32) -> Rgbw {
+        let [r, g, b, w] = bytes.to_ne_bytes();
+        Rgbw::new(r, g, b, w)
+    }
+}
+
+/// Unpacks a `0xRRGGBBWW` value.
+impl From<u32> for Rgbw {
+    fn from(value: u32) -> Self {
+        let [r, g, b, w] = value.to_be_bytes();
+        Rgbw::new(r, g, b, w)
+    }
+}
+
+/// Packs the color as `0xRRGGBBWW`.
+impl From<Rgbw> for u32 {
+    fn from(color: Rgbw) -> Self {
+        u32::from_be_bytes([color.r, color.g, color.b, color.w])
+    }
+}
+
+/// Formats the color as `#rrggbbww`.
+impl fmt::Display for Rgbw {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:08x}", u32::from(*self))
+    }
+}
+
+/// Parses `#rrggbbww`, `#rrggbb` or a CSS color name. CSS colors leave the white channel off.
+///
+/// # Example
+/// ```
+/// use matrix_rhal::Rgbw;
+///
+/// assert_eq!("#ff000010".parse::<Rgbw>().unwrap(), Rgbw::new(255, 0, 0, 16));
+/// assert_eq!("#00ff00".parse::<Rgbw>().unwrap(), Rgbw::new(0, 255, 0, 0));
+/// assert_eq!("Orange".parse::<Rgbw>().unwrap(), Rgbw::new(255, 165, 0, 0));
+/// ```
+impl FromStr for Rgbw {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidColor(value.to_string());
+
+        if let Some(hex) = value.strip_prefix('#') {
+            let packed = match hex.len() {
+                6 => u32::from_str_radix(hex, 16).map(|rgb| rgb << 8),
+                8 => u32::from_str_radix(hex, 16),
+                _ => return Err(invalid()),
+            };
+
+            // `from_str_radix` accepts a leading sign, which is not a valid color
+            return match packed {
+                Ok(packed) if hex.chars().all(|digit| digit.is_ascii_hexdigit()) => {
+                    Ok(Rgbw::from(packed))
+                }
+                _ => Err(invalid()),
+            };
+        }
+
+        let name = value.to_ascii_lowercase();
+        CSS_COLORS
+            .binary_search_by(|(css_name, _)| css_name.cmp(&name.as_str()))
+            .map(|index| {
+                let [r, g, b] = CSS_COLORS[index].1;
+                Rgbw::new(r, g, b, 0)
+            })
+            .map_err(|_| invalid())
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serialization {
+    use super::Rgbw;
+    use serde::de::{self, Deserializer, Visitor};
+    use serde::ser::Serializer;
+    use serde::{Deserialize, Serialize};
+    use std::fmt;
+
+    /// Serializes as a `#rrggbbww` string.
+    impl Serialize for Rgbw {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_str(self)
+        }
+    }
+
+    /// Accepts anything `Rgbw::from_str` does.
+    impl<'de> Deserialize<'de> for Rgbw {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct ColorVisitor;
+
+            impl<'de> Visitor<'de> for ColorVisitor {
+                type Value = Rgbw;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    write!(f, "a \"#rrggbbww\", \"#rrggbb\" or CSS color name")
+                }
+
+                fn visit_str<E: de::Error>(self, value: &str) -> Result<Rgbw, E> {
+                    value.parse().map_err(E::custom)
+                }
+            }
+
+            deserializer.deserialize_str(ColorVisitor)
+        }
     }
 }
@@ -1,18 +1,41 @@
+pub mod animation;
+pub mod assistant;
+pub mod compositor;
+mod css_colors;
+pub mod display;
+pub mod dither;
+pub mod geometry;
 mod led;
+pub mod orientation;
+pub mod preview;
+pub mod visualizer;
+#[cfg(feature = "smart-leds")]
+mod smart_leds;
 use crate::bus::memory_map::*;
-use crate::Bus;
+use crate::{Bus, Error};
+pub use geometry::{Direction, Geometry};
 pub use led::Rgbw;
 
 /// Controls the ring of LEDS on a MATRIX device.
 #[derive(Debug)]
 pub struct Everloop<'a> {
     bus: &'a Bus,
+    geometry: Geometry,
 }
 
 impl<'a> Everloop<'a> {
     /// Return an instance of Everloop.
     pub fn new(bus: &Bus) -> Everloop {
-        Everloop { bus }
+        let geometry = Geometry::for_device(&bus.device_name).unwrap_or_else(|| {
+            Geometry::new(bus.device_leds as usize, 0.0, Direction::CounterClockwise)
+        });
+
+        Everloop { bus, geometry }
+    }
+
+    /// Return the physical layout of the LEDs on the current MATRIX device.
+    pub fn geometry(&self) -> Geometry {
+        self.geometry
     }
 
     /// Map each `RGBW` to the respective MATRIX LED. LEDs not set are defaulted to black.
@@ -25,38 +48,131 @@ impl<'a> Everloop<'a> {
     /// everloop.set(&vec![matrix_rhal::Rgbw::new(0,0,255,0); 15]);
     /// ```
     pub fn set(&self, leds: &[Rgbw]) {
-        if leds.len() > self.bus.device_leds as usize {
-            panic!(
-                "Invalid LED set. This device only has {} LEDs",
-                self.bus.device_leds
-            );
+        if let Err(error) = self.try_set(leds) {
+            panic!("{}", error);
+        }
+    }
+
+    /// Fallible version of `set`. Returns an error if more LEDs are given than the device has.
+    pub fn try_set(&self, leds: &[Rgbw]) -> Result<(), Error> {
+        let device_leds = self.bus.device_leds as usize;
+        if leds.len() > device_leds {
+            return Err(Error::InvalidLedRange);
+        }
+
+        // set remaining LEDs to black
+        let mut frame = leds.to_vec();
+        frame.resize(device_leds, Rgbw::black());
+
+        self.set_range(0, &frame)
+    }
+
+    /// Update only the LEDs starting at `offset`. Every other LED keeps its current color.
+    ///
+    /// This allows separate components to each own a portion of the ring.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # let bus = matrix_rhal::Bus::init().unwrap();
+    /// let everloop = matrix_rhal::Everloop::new(&bus);
+    /// // Turn LEDs 2 and 3 red without touching the rest
+    /// everloop.set_range(2, &[matrix_rhal::Rgbw::new(255, 0, 0, 0); 2]).unwrap();
+    /// ```
+    ///
+    /// Ranges past the last LED are rejected before anything is written:
+    /// ```
+    /// # let bus = matrix_rhal::Bus { device_file: "/dev/null", regmap_fd: -1, device_name: matrix_rhal::Device::Creator, device_version: 0, device_leds: 35, fpga_frequency: 0 };
+    /// let everloop = matrix_rhal::Everloop::new(&bus);
+    /// let red = [matrix_rhal::Rgbw::new(255, 0, 0, 0); 2];
+    /// assert!(matches!(everloop.set_range(34, &red), Err(matrix_rhal::Error::InvalidLedRange)));
+    /// assert!(matches!(everloop.set_range(usize::MAX, &red), Err(matrix_rhal::Error::InvalidLedRange)));
+    /// ```
+    pub fn set_range(&self, offset: usize, leds: &[Rgbw]) -> Result<(), Error> {
+        match offset.checked_add(leds.len()) {
+            Some(end) if end <= self.bus.device_leds as usize => {}
+            _ => return Err(Error::InvalidLedRange),
         }
 
         // create write buffer
-        let mut request = Vec::with_capacity(self.bus.device_leds as usize + 2);
-        request.push(fpga_address::EVERLOOP as i32);
-        request.push((self.bus.device_leds * 4) as i32); // each LED RGBW requires 4 bytes
+        // FPGA addresses are 16-bit words, so each LED (4 bytes) takes up 2 addresses
+        let mut request = Vec::with_capacity(leds.len() + 2);
+        request.push((fpga_address::EVERLOOP as usize + offset * 2) as i32);
+        request.push((leds.len() * 4) as i32); // each LED RGBW requires 4 bytes
 
         // store all LED colors given
         for led in leds {
             request.push(led.as_bytes());
         }
 
-        // set remaining LEDs to black
-        for _ in 0..(request.capacity() - request.len()) {
-            request.push(Rgbw::black().as_bytes())
-        }
-
         // render LEDs
         self.bus
-            .write(unsafe { std::mem::transmute::<&mut Vec<i32>, &mut Vec<u8>>(&mut request) });
+            .try_write(unsafe { std::mem::transmute::<&mut [i32], &mut [u8]>(&mut request) })
+    }
+
+    /// Return the colors currently being shown on the MATRIX LEDs.
+    ///
+    /// The LED buffer is read back from the FPGA, so this reflects writes made by any process.
+    pub fn get(&self) -> Vec<Rgbw> {
+        self.try_get().expect("error reading the Everloop")
+    }
+
+    /// Fallible version of `get`.
+    pub fn try_get(&self) -> Result<Vec<Rgbw>, Error> {
+        let leds = self.bus.device_leds as usize;
+
+        // create read buffer
+        let mut data: Vec<i32> = vec![0; leds + 2];
+        data[0] = fpga_address::EVERLOOP as i32;
+        data[1] = (leds * 4) as i32; // each LED RGBW requires 4 bytes
+
+        // populate buffer
+        self.bus
+            .try_read(unsafe { std::mem::transmute::<&mut [i32], &mut [u8]>(&mut data) })?;
+
+        // returned LEDs start at data[2]
+        Ok(data[2..]
+            .iter()
+            .map(|bytes| Rgbw::from_bytes(*bytes))
+            .collect())
     }
 
     /// Set all MATRIX LEDs to a single color
     pub fn set_all(&self, color: Rgbw) {
-        let mut leds = Vec::new();
-        leds.extend(std::iter::repeat(color).take(self.bus.device_leds as usize));
+        if let Err(error) = self.try_set_all(color) {
+            panic!("{}", error);
+        }
+    }
+
+    /// Fallible version of `set_all`.
+    pub fn try_set_all(&self, color: Rgbw) -> Result<(), Error> {
+        self.try_set(&vec![color; self.bus.device_leds as usize])
+    }
+
+    /// Light the LEDs pointing to `angle` (degrees from the board's X axis). See `Geometry::light_at_angle`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # let bus = matrix_rhal::Bus::init().unwrap();
+    /// let everloop = matrix_rhal::Everloop::new(&bus);
+    /// // Point a 30 degree wide green beam at 90 degrees
+    /// everloop.light_at_angle(90.0, 30.0, matrix_rhal::Rgbw::new(0, 255, 0, 0));
+    /// ```
+    pub fn light_at_angle(&self, angle: f32, width: f32, color: Rgbw) {
+        self.set(&self.geometry.light_at_angle(angle, width, color))
+    }
+
+    /// Fallible version of `light_at_angle`.
+    pub fn try_light_at_angle(&self, angle: f32, width: f32, color: Rgbw) -> Result<(), Error> {
+        self.try_set(&self.geometry.light_at_angle(angle, width, color))
+    }
+
+    /// Light an arc sweeping counter-clockwise from `start` to `end` (degrees). See `Geometry::arc`.
+    pub fn set_arc(&self, start: f32, end: f32, color: Rgbw) {
+        self.set(&self.geometry.arc(start, end, color))
+    }
 
-        self.set(&leds)
+    /// Fallible version of `set_arc`.
+    pub fn try_set_arc(&self, start: f32, end: f32, color: Rgbw) -> Result<(), Error> {
+        self.try_set(&self.geometry.arc(start, end, color))
     }
 }
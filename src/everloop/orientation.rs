@@ -0,0 +1,99 @@
+use super::{Everloop, Rgbw};
+use crate::sensors::{Imu, Sensors};
+use crate::Error;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What the ring shows while in an orientation mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Light the LEDs pointing to magnetic north.
+    Compass,
+    /// Move a dot toward the lowest edge of the board. The whole ring lights up once the board is level.
+    Level,
+}
+
+/// Shows the board's orientation on the Everloop using the IMU. Only available on the MATRIX Creator.
+///
+/// # Example
+/// ```no_run
+/// use matrix_rhal::everloop::orientation::{Mode, OrientationRing};
+///
+/// # let bus = matrix_rhal::Bus::init().unwrap();
+/// let everloop = matrix_rhal::Everloop::new(&bus);
+/// let sensors = matrix_rhal::Sensors::new(&bus);
+/// let ring = OrientationRing::new(&everloop, &sensors);
+///
+/// // refresh the compass 20 times per second, forever
+/// ring.run(Mode::Compass, 20.0, || true).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct OrientationRing<'a> {
+    everloop: &'a Everloop<'a>,
+    sensors: &'a Sensors<'a>,
+    /// Color of the compass needle and the level dot.
+    pub color: Rgbw,
+    /// Color of the ring once the board is level.
+    pub level_color: Rgbw,
+    /// Width, in degrees, of the compass needle and the level dot.
+    pub width: f32,
+    /// Largest tilt, in degrees, still considered level.
+    pub level_tolerance: f32,
+}
+
+impl<'a> OrientationRing<'a> {
+    /// Return an instance of OrientationRing with a red needle and a green level indicator.
+    pub fn new(everloop: &'a Everloop<'a>, sensors: &'a Sensors<'a>) -> OrientationRing<'a> {
+        OrientationRing {
+            everloop,
+            sensors,
+            color: Rgbw::new(255, 0, 0, 0),
+            level_color: Rgbw::new(0, 60, 0, 0),
+            width: 20.0,
+            level_tolerance: 1.0,
+        }
+    }
+
+    /// Build the frame for an IMU reading.
+    pub fn frame(&self, mode: Mode, imu: &Imu) -> Vec<Rgbw> {
+        let geometry = self.everloop.geometry();
+
+        match mode {
+            Mode::Compass => geometry.light_at_angle(imu.heading(), self.width, self.color),
+            Mode::Level => {
+                let (direction, tilt) = imu.tilt();
+                if tilt <= self.level_tolerance {
+                    vec![self.level_color; geometry.leds]
+                } else {
+                    geometry.light_at_angle(direction, self.width, self.color)
+                }
+            }
+        }
+    }
+
+    /// Read the IMU once and render the result.
+    pub fn update(&self, mode: Mode) -> Result<(), Error> {
+        let imu = self.sensors.try_read_imu()?;
+        self.everloop.try_set(&self.frame(mode, &imu))
+    }
+
+    /// Keep refreshing the ring `rate` times per second for as long as `keep_running` returns `true`.
+    pub fn run<F>(&self, mode: Mode, rate: f32, mut keep_running: F) -> Result<(), Error>
+    where
+        F: FnMut() -> bool,
+    {
+        let period = Duration::from_secs_f32(1.0 / rate.max(0.01));
+
+        while keep_running() {
+            let started = Instant::now();
+            self.update(mode)?;
+
+            // sleep for whatever is left of this refresh
+            if let Some(remaining) = period.checked_sub(started.elapsed()) {
+                thread::sleep(remaining);
+            }
+        }
+
+        Ok(())
+    }
+}
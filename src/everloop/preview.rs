@@ -0,0 +1,181 @@
+//! Preview Everloop frames without hardware.
+//!
+//! Frames can be drawn as a true-color ANSI ring in the terminal. With the `images` feature, frames and whole
+//! animations can also be exported as PNG/GIF images using the LED layout of the device.
+use super::{Geometry, Rgbw};
+
+/// Color the LED appears as on screen. The white channel is added on top of the RGB channels.
+pub fn display_color(led: Rgbw) -> [u8; 3] {
+    [
+        led.r.saturating_add(led.w),
+        led.g.saturating_add(led.w),
+        led.b.saturating_add(led.w),
+    ]
+}
+
+/// Draw a frame as a ring of true-color ANSI characters, laid out the same way as the LEDs on the device.
+///
+/// # Example
+/// ```
+/// use matrix_rhal::everloop::{preview, Direction, Geometry, Rgbw};
+///
+/// let geometry = Geometry::new(18, 0.0, Direction::CounterClockwise);
+/// let frame = geometry.light_at_angle(90.0, 60.0, Rgbw::new(0, 0, 255, 0));
+///
+/// println!("{}", preview::render_ansi(&frame, &geometry));
+/// ```
+pub fn render_ansi(frame: &[Rgbw], geometry: &Geometry) -> String {
+    // terminal cells are about twice as tall as they are wide
+    let radius = (geometry.leds as f32 / 4.0).ceil().max(4.0);
+    let rows = (radius * 2.0) as usize + 1;
+    let columns = (radius * 4.0) as usize + 1;
+
+    let mut grid: Vec<Vec<Option<Rgbw>>> = vec![vec![None; columns]; rows];
+    for (index, led) in frame.iter().take(geometry.leds).enumerate() {
+        let angle = geometry.led_angle(index).to_radians();
+        let column = (radius * 2.0 + radius * 2.0 * angle.cos()).round() as usize;
+        let row = (radius - radius * angle.sin()).round() as usize;
+        grid[row][column] = Some(*led);
+    }
+
+    let mut output = String::new();
+    for row in grid {
+        for cell in row {
+            match cell {
+                // LEDs that are off are drawn hollow so the ring stays visible
+                Some(led) if display_color(led) == [0, 0, 0] => output.push('\u{25cb}'),
+                Some(led) => {
+                    let [r, g, b] = display_color(led);
+                    output.push_str(&format!("\x1b[38;2;{};{};{}m\u{25cf}\x1b[0m", r, g, b));
+                }
+                None => output.push(' '),
+            }
+        }
+        output.push('\n');
+    }
+
+    output
+}
+
+/// Draw a frame into a `size` x `size` RGBA image.
+pub fn rasterize(frame: &[Rgbw], geometry: &Geometry, size: u32) -> Vec<u8> {
+    const BACKGROUND: [u8; 4] = [20, 20, 20, 255];
+
+    let center = size as f32 / 2.0;
+    let ring_radius = size as f32 * 0.4;
+    // keep neighboring LEDs from overlapping
+    let led_radius = (ring_radius * (geometry.spacing().to_radians() / 2.0).sin() * 0.8).max(1.0);
+
+    let leds: Vec<(f32, f32, [u8; 3])> = frame
+        .iter()
+        .take(geometry.leds)
+        .enumerate()
+        .map(|(index, led)| {
+            let angle = geometry.led_angle(index).to_radians();
+            (
+                center + ring_radius * angle.cos(),
+                center - ring_radius * angle.sin(),
+                display_color(*led),
+            )
+        })
+        .collect();
+
+    let mut image = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let (x, y) = (x as f32 + 0.5, y as f32 + 0.5);
+            let pixel = leds
+                .iter()
+                .find(|(led_x, led_y, _)| (x - led_x).hypot(y - led_y) <= led_radius)
+                .map_or(BACKGROUND, |(_, _, [r, g, b])| [*r, *g, *b, 255]);
+
+            image.extend_from_slice(&pixel);
+        }
+    }
+
+    image
+}
+
+#[cfg(feature = "images")]
+mod image {
+    use super::rasterize;
+    use crate::everloop::{animation::Animation, Geometry, Rgbw};
+    use crate::Error;
+    use std::convert::TryFrom;
+    use std::io::Write;
+
+    /// Export a frame as a `size` x `size` PNG.
+    pub fn write_png<W: Write>(
+        writer: W,
+        frame: &[Rgbw],
+        geometry: &Geometry,
+        size: u32,
+    ) -> Result<(), Error> {
+        let mut encoder = png::Encoder::new(writer, size, size);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&rasterize(frame, geometry, size)))
+            .map_err(|error| Error::Any(Box::new(error)))
+    }
+
+    /// Export an animation as a `size` x `size` GIF. GIF frame delays have a precision of 10ms.
+    ///
+    /// GIF dimensions are 16 bits, so `size` cannot go above 65535. Longer delays and loop counts than GIF can
+    /// store are saturated.
+    ///
+    /// # Example
+    /// ```
+    /// use matrix_rhal::everloop::animation::{Animation, Frame};
+    /// use matrix_rhal::everloop::preview::write_gif;
+    /// use matrix_rhal::everloop::{Direction, Geometry};
+    /// use matrix_rhal::Rgbw;
+    /// use std::time::Duration;
+    ///
+    /// let geometry = Geometry::new(18, 0.0, Direction::Clockwise);
+    /// let frame = Frame { leds: vec![Rgbw::new(255, 0, 0, 0); 18], duration: Duration::from_millis(100) };
+    /// let animation = Animation::new(vec![frame], None);
+    ///
+    /// let mut gif = Vec::new();
+    /// write_gif(&mut gif, &animation, &geometry, 64).unwrap();
+    /// assert!(gif.starts_with(b"GIF89a"));
+    ///
+    /// assert!(write_gif(Vec::new(), &animation, &geometry, 70_000).is_err());
+    /// ```
+    pub fn write_gif<W: Write>(
+        writer: W,
+        animation: &Animation,
+        geometry: &Geometry,
+        size: u32,
+    ) -> Result<(), Error> {
+        let side = u16::try_from(size).map_err(|error| Error::Any(Box::new(error)))?;
+
+        let encode = || -> Result<(), gif::EncodingError> {
+            let mut encoder = gif::Encoder::new(writer, side, side, &[])?;
+
+            let repeat = match animation.loops {
+                Some(loops) => {
+                    gif::Repeat::Finite(u16::try_from(loops.saturating_sub(1)).unwrap_or(u16::MAX))
+                }
+                None => gif::Repeat::Infinite,
+            };
+            encoder.set_repeat(repeat)?;
+
+            for frame in &animation.frames {
+                let mut pixels = rasterize(&frame.leds, geometry, size);
+                let mut image = gif::Frame::from_rgba_speed(side, side, &mut pixels, 10);
+                image.delay = u16::try_from(frame.duration.as_millis() / 10).unwrap_or(u16::MAX);
+                encoder.write_frame(&image)?;
+            }
+
+            Ok(())
+        };
+
+        encode().map_err(|error| Error::Any(Box::new(error)))
+    }
+}
+
+#[cfg(feature = "images")]
+pub use self::image::*;
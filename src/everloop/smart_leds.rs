@@ -0,0 +1,78 @@
+//! Integration with the `smart-leds` ecosystem.
+//!
+//! The Everloop has no transparency, so `RGBA8` colors are blended over black: alpha scales the other channels.
+use super::{Everloop, Rgbw};
+use crate::Error;
+use rgb::{RGB8, RGBA8};
+use smart_leds_trait::{SmartLedsWrite, White, RGBW};
+
+/// Lets `smart-leds` effects and drivers write to the Everloop. LEDs not given are set to black.
+///
+/// Any color that converts into `Rgbw`, such as `RGB8`, `RGBA8` or `RGBW<u8>`, can be written.
+impl<'a> SmartLedsWrite for Everloop<'a> {
+    type Error = Error;
+    type Color = Rgbw;
+
+    fn write<T, I>(&mut self, iterator: T) -> Result<(), Self::Error>
+    where
+        T: IntoIterator<Item = I>,
+        I: Into<Self::Color>,
+    {
+        let leds: Vec<Rgbw> = iterator.into_iter().map(Into::into).collect();
+        self.try_set(&leds)
+    }
+}
+
+/// The white channel is set to 0.
+impl From<RGB8> for Rgbw {
+    fn from(color: RGB8) -> Self {
+        Rgbw::new(color.r, color.g, color.b, 0)
+    }
+}
+
+/// The white channel is dropped.
+impl From<Rgbw> for RGB8 {
+    fn from(color: Rgbw) -> Self {
+        RGB8::new(color.r, color.g, color.b)
+    }
+}
+
+/// Alpha scales the color towards black and the white channel is set to 0.
+///
+/// # Example
+/// ```
+/// use matrix_rhal::Rgbw;
+/// use rgb::RGBA8;
+///
+/// assert_eq!(Rgbw::from(RGBA8::new(255, 100, 0, 51)), Rgbw::new(51, 20, 0, 0));
+/// ```
+impl From<RGBA8> for Rgbw {
+    fn from(color: RGBA8) -> Self {
+        let scale = |channel: u8| (channel as u16 * color.a as u16 / 255) as u8;
+        Rgbw::new(scale(color.r), scale(color.g), scale(color.b), 0)
+    }
+}
+
+/// The color is fully opaque and the white channel is dropped.
+impl From<Rgbw> for RGBA8 {
+    fn from(color: Rgbw) -> Self {
+        RGBA8::new(color.r, color.g, color.b, 255)
+    }
+}
+
+impl From<RGBW<u8>> for Rgbw {
+    fn from(color: RGBW<u8>) -> Self {
+        Rgbw::new(color.r, color.g, color.b, color.a.0)
+    }
+}
+
+impl From<Rgbw> for RGBW<u8> {
+    fn from(color: Rgbw) -> Self {
+        RGBW {
+            r: color.r,
+            g: color.g,
+            b: color.b,
+            a: White(color.w),
+        }
+    }
+}
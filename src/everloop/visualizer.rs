@@ -0,0 +1,349 @@
+//! Audio-reactive Everloop visualizer.
+//!
+//! PCM samples, from the microphone array or any other source, are fed into a `Visualizer` which computes the
+//! overall level, per-band energies (FFT) and beats. The result can then be drawn as a VU meter, spectrum bars
+//! around the ring or a flash on every beat.
+use super::{Everloop, Geometry, Rgbw};
+use crate::Error;
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+/// How audio is drawn on the ring.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// The ring fills up with the overall loudness.
+    VuMeter,
+    /// Frequency bands are spread around the ring, each LED showing the energy of its band.
+    Spectrum,
+    /// Every LED flashes on a beat and fades out.
+    BeatFlash,
+}
+
+/// Settings for a `Visualizer`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Config {
+    /// Sample rate of the PCM frames, in Hz.
+    pub sample_rate: u32,
+    /// Number of frequency bands used in `Spectrum` mode.
+    pub bands: usize,
+    /// Lowest frequency (Hz) shown in `Spectrum` mode.
+    pub min_frequency: f32,
+    /// Highest frequency (Hz) shown in `Spectrum` mode.
+    pub max_frequency: f32,
+    /// Quietest level shown, in dBFS. Anything quieter appears as off.
+    pub floor_db: f32,
+    /// How quickly levels rise, from `0.0` (never) to `1.0` (instantly).
+    pub attack: f32,
+    /// How quickly levels fall, from `0.0` (never) to `1.0` (instantly).
+    pub decay: f32,
+    /// How much louder than the recent average the bass must be to count as a beat.
+    pub beat_sensitivity: f32,
+    /// Color of quiet levels.
+    pub color: Rgbw,
+    /// Color of loud levels.
+    pub peak_color: Rgbw,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            sample_rate: 16000,
+            bands: 12,
+            min_frequency: 60.0,
+            max_frequency: 6000.0,
+            floor_db: -60.0,
+            attack: 0.6,
+            decay: 0.15,
+            beat_sensitivity: 1.5,
+            color: Rgbw::new(0, 255, 0, 0),
+            peak_color: Rgbw::new(255, 0, 0, 0),
+        }
+    }
+}
+
+/// Number of frames used to average the bass energy for beat detection (about a second at 40 frames per second).
+const BEAT_HISTORY: usize = 43;
+
+/// Turns PCM frames into Everloop frames.
+///
+/// # Example
+/// ```no_run
+/// use matrix_rhal::everloop::visualizer::{Config, Mode, Visualizer};
+///
+/// # let bus = matrix_rhal::Bus::init().unwrap();
+/// # let next_pcm_frame = || vec![0i16; 512];
+/// let everloop = matrix_rhal::Everloop::new(&bus);
+/// let mut visualizer = Visualizer::new(Mode::Spectrum, Config::default()).unwrap();
+///
+/// loop {
+///     visualizer.process_i16(&next_pcm_frame());
+///     visualizer.render(&everloop).unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Visualizer {
+    mode: Mode,
+    config: Config,
+    level: f32,
+    bands: Vec<f32>,
+    bass_history: VecDeque<f32>,
+    beat: bool,
+    flash: f32,
+}
+
+impl Visualizer {
+    /// Return an instance of Visualizer with every level at 0.
+    ///
+    /// Fails unless `sample_rate`, `bands` and `min_frequency` are positive, `min_frequency` is below
+    /// `max_frequency` and `floor_db` is negative.
+    ///
+    /// # Example
+    /// ```
+    /// use matrix_rhal::everloop::visualizer::{Config, Mode, Visualizer};
+    /// use std::f32::consts::PI;
+    ///
+    /// let mut visualizer = Visualizer::new(Mode::Spectrum, Config::default()).unwrap();
+    ///
+    /// // a 1kHz sine wave sampled at 16kHz
+    /// let samples: Vec<f32> = (0..512)
+    ///     .map(|index| (2.0 * PI * 1000.0 * index as f32 / 16000.0).sin())
+    ///     .collect();
+    /// visualizer.process(&samples);
+    ///
+    /// // the default 12 bands go from 60Hz to 6kHz, and band 7 covers 880Hz to 1.3kHz
+    /// let bands = visualizer.bands();
+    /// let loudest = (0..bands.len())
+    ///     .max_by(|a, b| bands[*a].total_cmp(&bands[*b]))
+    ///     .unwrap();
+    /// assert_eq!(loudest, 7);
+    ///
+    /// let silent = Config {
+    ///     floor_db: 0.0,
+    ///     ..Config::default()
+    /// };
+    /// assert!(Visualizer::new(Mode::VuMeter, silent).is_err());
+    /// ```
+    pub fn new(mode: Mode, config: Config) -> Result<Visualizer, Error> {
+        let invalid = |reason: &str| Err(Error::InvalidVisualizerConfig(reason.to_string()));
+
+        if config.sample_rate == 0 {
+            return invalid("the sample rate must be positive");
+        }
+        if config.bands == 0 {
+            return invalid("there must be at least one band");
+        }
+        // also rejects NaN
+        if !(config.min_frequency > 0.0 && config.min_frequency < config.max_frequency) {
+            return invalid("frequencies must satisfy 0 < min_frequency < max_frequency");
+        }
+        if config.floor_db.is_nan() || config.floor_db >= 0.0 {
+            return invalid("floor_db must be negative");
+        }
+
+        Ok(Visualizer {
+            mode,
+            config,
+            level: 0.0,
+            bands: vec![0.0; config.bands],
+            bass_history: VecDeque::with_capacity(BEAT_HISTORY),
+            beat: false,
+            flash: 0.0,
+        })
+    }
+
+    /// Change how audio is drawn on the ring.
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    /// Smoothed overall loudness, from `0.0` to `1.0`.
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    /// Smoothed energy of each frequency band, from `0.0` to `1.0`, ordered from low to high frequencies.
+    pub fn bands(&self) -> &[f32] {
+        &self.bands
+    }
+
+    /// Whether the last frame processed contained a beat.
+    pub fn is_beat(&self) -> bool {
+        self.beat
+    }
+
+    /// Feed a frame of 16-bit PCM samples.
+    pub fn process_i16(&mut self, samples: &[i16]) {
+        let samples: Vec<f32> = samples
+            .iter()
+            .map(|sample| *sample as f32 / i16::MAX as f32)
+            .collect();
+
+        self.process(&samples)
+    }
+
+    /// Feed a frame of PCM samples, ranging from `-1.0` to `1.0`.
+    pub fn process(&mut self, samples: &[f32]) {
+        if samples.is_empty() {
+            return;
+        }
+
+        // overall loudness
+        let rms = (samples.iter().map(|sample| sample * sample).sum::<f32>()
+            / samples.len() as f32)
+            .sqrt();
+        self.level = self.smooth(self.level, self.normalize_db(rms));
+
+        // frequency bands
+        let spectrum = magnitude_spectrum(samples);
+        let bin_width = self.config.sample_rate as f32 / (spectrum.len() * 2) as f32;
+        for band in 0..self.config.bands {
+            let (low, high) = self.band_range(band);
+            let first = ((low / bin_width) as usize).min(spectrum.len() - 1);
+            let last = ((high / bin_width).ceil() as usize).clamp(first + 1, spectrum.len());
+
+            let magnitude = spectrum[first..last].iter().copied().fold(0.0, f32::max);
+            self.bands[band] = self.smooth(self.bands[band], self.normalize_db(magnitude));
+        }
+
+        // beats are detected from sudden jumps of bass energy
+        let bass_limit = ((150.0 / bin_width).ceil() as usize).clamp(1, spectrum.len());
+        let bass: f32 = spectrum[..bass_limit].iter().map(|bin| bin * bin).sum();
+        let average = self.bass_history.iter().sum::<f32>() / self.bass_history.len().max(1) as f32;
+
+        self.beat = self.bass_history.len() == BEAT_HISTORY
+            && bass > average * self.config.beat_sensitivity
+            && bass > f32::EPSILON;
+        if self.bass_history.len() == BEAT_HISTORY {
+            self.bass_history.pop_front();
+        }
+        self.bass_history.push_back(bass);
+
+        self.flash = if self.beat {
+            1.0
+        } else {
+            self.flash * (1.0 - self.config.decay)
+        };
+    }
+
+    /// Build the frame for the current levels.
+    pub fn frame(&self, geometry: &Geometry) -> Vec<Rgbw> {
+        let leds = geometry.leds;
+        let gradient = |amount: f32| self.config.color.lerp(self.config.peak_color, amount);
+
+        match self.mode {
+            Mode::VuMeter => {
+                let lit = self.level * leds as f32;
+                (0..leds)
+                    .map(|led| {
+                        let amount = led as f32 / leds.max(1) as f32;
+                        gradient(amount).scale(lit - led as f32)
+                    })
+                    .collect()
+            }
+            Mode::Spectrum if self.bands.is_empty() => vec![Rgbw::black(); leds],
+            Mode::Spectrum => (0..leds)
+                .map(|led| {
+                    let band = (led * self.bands.len() / leds).min(self.bands.len() - 1);
+                    let level = self.bands[band];
+                    gradient(level).scale(level)
+                })
+                .collect(),
+            Mode::BeatFlash => vec![gradient(self.flash).scale(self.flash); leds],
+        }
+    }
+
+    /// Draw the current levels on the Everloop.
+    pub fn render(&self, everloop: &Everloop) -> Result<(), Error> {
+        everloop.try_set(&self.frame(&everloop.geometry()))
+    }
+
+    /// Frequency range (Hz) of a band. Bands are spaced logarithmically.
+    fn band_range(&self, band: usize) -> (f32, f32) {
+        let ratio = self.config.max_frequency / self.config.min_frequency;
+        let edge = |index: usize| {
+            self.config.min_frequency * ratio.powf(index as f32 / self.config.bands as f32)
+        };
+
+        (edge(band), edge(band + 1))
+    }
+
+    /// Map an amplitude (`0.0..=1.0`) to `0.0..=1.0` between `floor_db` and 0 dBFS.
+    fn normalize_db(&self, amplitude: f32) -> f32 {
+        let db = 20.0 * amplitude.max(f32::MIN_POSITIVE).log10();
+        (1.0 - db / self.config.floor_db).clamp(0.0, 1.0)
+    }
+
+    fn smooth(&self, previous: f32, next: f32) -> f32 {
+        let rate = if next > previous {
+            self.config.attack
+        } else {
+            self.config.decay
+        };
+
+        previous + (next - previous) * rate.clamp(0.0, 1.0)
+    }
+}
+
+/// Return the magnitude of each frequency bin, from 0Hz up to half of the sample rate.
+///
+/// Samples are windowed and zero padded to the next power of two. Magnitudes are scaled so that a full scale
+/// sine wave peaks at about `1.0`.
+fn magnitude_spectrum(samples: &[f32]) -> Vec<f32> {
+    let size = samples.len().next_power_of_two().max(2);
+
+    // Hann window
+    let mut buffer: Vec<(f32, f32)> = (0..size)
+        .map(|index| {
+            let sample = samples.get(index).copied().unwrap_or(0.0);
+            let window = 0.5 - 0.5 * (2.0 * PI * index as f32 / samples.len() as f32).cos();
+            (sample * window, 0.0)
+        })
+        .collect();
+
+    fft(&mut buffer);
+
+    // the window halves the amplitude, and only half of the bins are kept
+    let scale = 4.0 / samples.len() as f32;
+    buffer[..size / 2]
+        .iter()
+        .map(|(re, im)| re.hypot(*im) * scale)
+        .collect()
+}
+
+/// In-place radix-2 FFT. The buffer length must be a power of two.
+fn fft(buffer: &mut [(f32, f32)]) {
+    let size = buffer.len();
+
+    // bit reversal permutation
+    let mut j = 0;
+    for i in 1..size {
+        let mut bit = size >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+
+        if i < j {
+            buffer.swap(i, j);
+        }
+    }
+
+    // butterflies
+    let mut length = 2;
+    while length <= size {
+        let angle = -2.0 * PI / length as f32;
+        for start in (0..size).step_by(length) {
+            for k in 0..length / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (re, im) = buffer[start + k + length / 2];
+                let twiddled = (re * cos - im * sin, re * sin + im * cos);
+                let even = buffer[start + k];
+
+                buffer[start + k] = (even.0 + twiddled.0, even.1 + twiddled.1);
+                buffer[start + k + length / 2] = (even.0 - twiddled.0, even.1 - twiddled.1);
+            }
+        }
+        length <<= 1;
+    }
+}
@@ -37,7 +37,7 @@ impl<'a> Gpio<'a> {
     }
 
     /// A quick check to make sure a selected pin exists. Pins available are from `0-15`.
-    fn is_pin_valid(pin: u8) -> Result<(), Error> {
+    pub(crate) fn is_pin_valid(pin: u8) -> Result<(), Error> {
         if pin > 15 {
             return Err(Error::InvalidGpioPin);
         }
@@ -1,8 +1,10 @@
 pub mod bus;
 mod error;
-mod everloop;
+pub mod everloop;
 pub mod gpio;
-mod sensors;
+pub mod sensors;
+#[cfg(feature = "async")]
+pub mod worker;
 
 pub use bus::Bus;
 pub use error::Error;
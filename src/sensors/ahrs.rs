@@ -0,0 +1,410 @@
+//! Host-side attitude and heading estimation (AHRS).
+//!
+//! The MCU reports its own yaw, pitch and roll, but its filter cannot be tuned and Euler angles lock up near ±90°
+//! of pitch. `Ahrs` instead fuses the raw accelerometer, gyroscope and magnetometer readings with either the
+//! Madgwick or the Mahony filter and keeps the orientation as a quaternion.
+use super::units::Degrees;
+use super::Imu;
+use std::ops::Mul;
+
+/// A rotation, stored as a unit quaternion.
+///
+/// Orientations estimated by `Ahrs` rotate vectors from the board's frame into the earth's frame, where Z points up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Quaternion::identity()
+    }
+}
+
+impl Quaternion {
+    /// Shorthand way to create a Quaternion instance.
+    pub fn new(w: f32, x: f32, y: f32, z: f32) -> Quaternion {
+        Quaternion { w, x, y, z }
+    }
+
+    /// A rotation that does nothing.
+    pub fn identity() -> Quaternion {
+        Quaternion::new(1.0, 0.0, 0.0, 0.0)
+    }
+
+    /// Build a rotation from Euler angles, applied in yaw (Z), pitch (Y), roll (X) order.
+    pub fn from_euler(roll: Degrees, pitch: Degrees, yaw: Degrees) -> Quaternion {
+        let (sin_roll, cos_roll) = (roll.radians() / 2.0).sin_cos();
+        let (sin_pitch, cos_pitch) = (pitch.radians() / 2.0).sin_cos();
+        let (sin_yaw, cos_yaw) = (yaw.radians() / 2.0).sin_cos();
+
+        Quaternion::new(
+            cos_roll * cos_pitch * cos_yaw + sin_roll * sin_pitch * sin_yaw,
+            sin_roll * cos_pitch * cos_yaw - cos_roll * sin_pitch * sin_yaw,
+            cos_roll * sin_pitch * cos_yaw + sin_roll * cos_pitch * sin_yaw,
+            cos_roll * cos_pitch * sin_yaw - sin_roll * sin_pitch * cos_yaw,
+        )
+    }
+
+    pub fn norm(self) -> f32 {
+        (self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
+    }
+
+    /// Scale the quaternion to a length of 1. A zero quaternion becomes the identity.
+    pub fn normalize(self) -> Quaternion {
+        let norm = self.norm();
+        if norm <= f32::EPSILON || !norm.is_finite() {
+            return Quaternion::identity();
+        }
+
+        Quaternion::new(self.w / norm, self.x / norm, self.y / norm, self.z / norm)
+    }
+
+    /// The inverse rotation.
+    pub fn conjugate(self) -> Quaternion {
+        Quaternion::new(self.w, -self.x, -self.y, -self.z)
+    }
+
+    /// Rotate an `[x, y, z]` vector.
+    pub fn rotate(self, vector: [f32; 3]) -> [f32; 3] {
+        let matrix = self.rotation_matrix();
+        let mut rotated = [0.0; 3];
+        for (row, value) in matrix.iter().zip(rotated.iter_mut()) {
+            *value = row[0] * vector[0] + row[1] * vector[1] + row[2] * vector[2];
+        }
+
+        rotated
+    }
+
+    /// Row-major 3x3 rotation matrix of the quaternion.
+    pub fn rotation_matrix(self) -> [[f32; 3]; 3] {
+        let Quaternion { w, x, y, z } = self;
+
+        [
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+            ],
+        ]
+    }
+
+    /// Return `(roll, pitch, yaw)`, the inverse of `from_euler`. Pitch is limited to ±90°.
+    pub fn euler(self) -> (Degrees, Degrees, Degrees) {
+        let Quaternion { w, x, y, z } = self;
+
+        let roll = (2.0 * (w * x + y * z)).atan2(1.0 - 2.0 * (x * x + y * y));
+        let pitch = (2.0 * (w * y - z * x)).clamp(-1.0, 1.0).asin();
+        let yaw = (2.0 * (w * z + x * y)).atan2(1.0 - 2.0 * (y * y + z * z));
+
+        (
+            Degrees(roll.to_degrees()),
+            Degrees(pitch.to_degrees()),
+            Degrees(yaw.to_degrees()),
+        )
+    }
+}
+
+/// Hamilton product. `a * b` applies `b` first, then `a`.
+impl Mul for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, other: Quaternion) -> Quaternion {
+        Quaternion::new(
+            self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+            self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+        )
+    }
+}
+
+/// Filter used to fuse the IMU readings, along with its gains.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    /// Gradient descent filter. A higher `beta` trusts the accelerometer and magnetometer more than the gyroscope:
+    /// it converges faster but is noisier.
+    Madgwick { beta: f32 },
+    /// Complementary filter. `kp` sets how quickly the accelerometer and magnetometer correct the gyroscope and
+    /// `ki` how quickly gyroscope bias is learned (`0.0` disables it).
+    Mahony { kp: f32, ki: f32 },
+}
+
+impl Algorithm {
+    /// Madgwick filter with a `beta` of `0.1`.
+    pub fn madgwick() -> Algorithm {
+        Algorithm::Madgwick { beta: 0.1 }
+    }
+
+    /// Mahony filter with a `kp` of `0.5` and no bias correction.
+    pub fn mahony() -> Algorithm {
+        Algorithm::Mahony { kp: 0.5, ki: 0.0 }
+    }
+}
+
+/// Estimates the board's orientation from IMU readings taken at a known rate.
+///
+/// Readings with a zero magnetic field are fused without the magnetometer, in which case yaw drifts freely.
+///
+/// # Example
+/// ```
+/// use matrix_rhal::sensors::ahrs::{Ahrs, Algorithm};
+/// use matrix_rhal::sensors::units::GForce;
+/// use matrix_rhal::sensors::Imu;
+///
+/// // board resting with its X edge tilted 30 degrees down
+/// let imu = Imu {
+///     accel_x: GForce(-0.5),
+///     accel_z: GForce(0.866),
+///     ..Default::default()
+/// };
+///
+/// let mut ahrs = Ahrs::new(Algorithm::Madgwick { beta: 0.5 }, 100.0);
+/// for _ in 0..1000 {
+///     ahrs.update(&imu);
+/// }
+///
+/// let (_roll, pitch, _yaw) = ahrs.euler();
+/// assert!((pitch.0 - 30.0).abs() < 1.0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Ahrs {
+    algorithm: Algorithm,
+    sample_period: f32,
+    quaternion: Quaternion,
+    /// Integral of the Mahony error, in rad/s.
+    integral: [f32; 3],
+}
+
+impl Ahrs {
+    /// Return an instance of Ahrs for readings taken `sample_rate` times per second, starting level and facing
+    /// the board's X axis.
+    pub fn new(algorithm: Algorithm, sample_rate: f32) -> Ahrs {
+        Ahrs {
+            algorithm,
+            sample_period: 1.0 / sample_rate.max(f32::EPSILON),
+            quaternion: Quaternion::identity(),
+            integral: [0.0; 3],
+        }
+    }
+
+    /// Change the filter or its gains without losing the current orientation.
+    pub fn set_algorithm(&mut self, algorithm: Algorithm) {
+        self.algorithm = algorithm;
+    }
+
+    /// Forget the current orientation.
+    pub fn reset(&mut self) {
+        self.quaternion = Quaternion::identity();
+        self.integral = [0.0; 3];
+    }
+
+    /// Current orientation, rotating the board's frame into the earth's frame.
+    pub fn quaternion(&self) -> Quaternion {
+        self.quaternion
+    }
+
+    /// Current orientation as a row-major rotation matrix.
+    pub fn rotation_matrix(&self) -> [[f32; 3]; 3] {
+        self.quaternion.rotation_matrix()
+    }
+
+    /// Current orientation as `(roll, pitch, yaw)`.
+    pub fn euler(&self) -> (Degrees, Degrees, Degrees) {
+        self.quaternion.euler()
+    }
+
+    /// Fuse a reading taken one sample period after the previous one.
+    pub fn update(&mut self, imu: &Imu) {
+        self.update_with_period(imu, self.sample_period)
+    }
+
+    /// Fuse a reading taken `period` seconds after the previous one, for readings that are not evenly spaced.
+    pub fn update_with_period(&mut self, imu: &Imu, period: f32) {
+        let gyro = [
+            imu.gyro_x.radians_per_second(),
+            imu.gyro_y.radians_per_second(),
+            imu.gyro_z.radians_per_second(),
+        ];
+        let accel = normalize([imu.accel_x.0, imu.accel_y.0, imu.accel_z.0]);
+        let mag = normalize([imu.mag_x.0, imu.mag_y.0, imu.mag_z.0]);
+
+        if !gyro.iter().all(|value| value.is_finite()) {
+            return;
+        }
+
+        let quaternion = match self.algorithm {
+            Algorithm::Madgwick { beta } => self.madgwick(gyro, accel, mag, beta, period),
+            Algorithm::Mahony { kp, ki } => self.mahony(gyro, accel, mag, kp, ki, period),
+        };
+        self.quaternion = quaternion.normalize();
+    }
+
+    fn madgwick(
+        &self,
+        gyro: [f32; 3],
+        accel: Option<[f32; 3]>,
+        mag: Option<[f32; 3]>,
+        beta: f32,
+        period: f32,
+    ) -> Quaternion {
+        let q = self.quaternion;
+        let Quaternion { w, x, y, z } = q;
+
+        // rate of change from the gyroscope alone
+        let mut rate = q * Quaternion::new(0.0, gyro[0], gyro[1], gyro[2]);
+        rate = Quaternion::new(rate.w * 0.5, rate.x * 0.5, rate.y * 0.5, rate.z * 0.5);
+
+        if let Some([ax, ay, az]) = accel {
+            // objective function and Jacobian for gravity
+            let mut gradient = [0.0f32; 4];
+            let error = [
+                2.0 * (x * z - w * y) - ax,
+                2.0 * (w * x + y * z) - ay,
+                2.0 * (0.5 - x * x - y * y) - az,
+            ];
+            let jacobian = [
+                [-2.0 * y, 2.0 * z, -2.0 * w, 2.0 * x],
+                [2.0 * x, 2.0 * w, 2.0 * z, 2.0 * y],
+                [0.0, -4.0 * x, -4.0 * y, 0.0],
+            ];
+            accumulate(&mut gradient, &jacobian, &error);
+
+            if let Some([mx, my, mz]) = mag {
+                // earth's field only has a horizontal (north) and a vertical component
+                let h = q.rotate([mx, my, mz]);
+                let (bx, bz) = (h[0].hypot(h[1]), h[2]);
+
+                let error = [
+                    2.0 * bx * (0.5 - y * y - z * z) + 2.0 * bz * (x * z - w * y) - mx,
+                    2.0 * bx * (x * y - w * z) + 2.0 * bz * (w * x + y * z) - my,
+                    2.0 * bx * (w * y + x * z) + 2.0 * bz * (0.5 - x * x - y * y) - mz,
+                ];
+                let jacobian = [
+                    [
+                        -2.0 * bz * y,
+                        2.0 * bz * z,
+                        -4.0 * bx * y - 2.0 * bz * w,
+                        -4.0 * bx * z + 2.0 * bz * x,
+                    ],
+                    [
+                        -2.0 * bx * z + 2.0 * bz * x,
+                        2.0 * bx * y + 2.0 * bz * w,
+                        2.0 * bx * x + 2.0 * bz * z,
+                        -2.0 * bx * w + 2.0 * bz * y,
+                    ],
+                    [
+                        2.0 * bx * y,
+                        2.0 * bx * z - 4.0 * bz * x,
+                        2.0 * bx * w - 4.0 * bz * y,
+                        2.0 * bx * x,
+                    ],
+                ];
+                accumulate(&mut gradient, &jacobian, &error);
+            }
+
+            let norm = gradient
+                .iter()
+                .map(|value| value * value)
+                .sum::<f32>()
+                .sqrt();
+            if norm > f32::EPSILON {
+                rate.w -= beta * gradient[0] / norm;
+                rate.x -= beta * gradient[1] / norm;
+                rate.y -= beta * gradient[2] / norm;
+                rate.z -= beta * gradient[3] / norm;
+            }
+        }
+
+        Quaternion::new(
+            w + rate.w * period,
+            x + rate.x * period,
+            y + rate.y * period,
+            z + rate.z * period,
+        )
+    }
+
+    fn mahony(
+        &mut self,
+        mut gyro: [f32; 3],
+        accel: Option<[f32; 3]>,
+        mag: Option<[f32; 3]>,
+        kp: f32,
+        ki: f32,
+        period: f32,
+    ) -> Quaternion {
+        let q = self.quaternion;
+
+        if let Some(accel) = accel {
+            // where gravity should point in the board's frame, and how far the measurement is from it
+            let gravity = q.conjugate().rotate([0.0, 0.0, 1.0]);
+            let mut error = cross(accel, gravity);
+
+            if let Some(mag) = mag {
+                let h = q.rotate(mag);
+                let field = q.conjugate().rotate([h[0].hypot(h[1]), 0.0, h[2]]);
+                let mag_error = cross(mag, field);
+                for (error, mag_error) in error.iter_mut().zip(mag_error.iter()) {
+                    *error += mag_error;
+                }
+            }
+
+            for axis in 0..3 {
+                if ki > 0.0 {
+                    self.integral[axis] += ki * error[axis] * period;
+                } else {
+                    self.integral[axis] = 0.0;
+                }
+                gyro[axis] += kp * error[axis] + self.integral[axis];
+            }
+        }
+
+        let rate = q * Quaternion::new(0.0, gyro[0], gyro[1], gyro[2]);
+        Quaternion::new(
+            q.w + rate.w * 0.5 * period,
+            q.x + rate.x * 0.5 * period,
+            q.y + rate.y * 0.5 * period,
+            q.z + rate.z * 0.5 * period,
+        )
+    }
+}
+
+/// Add `jacobianᵀ · error` to `gradient`.
+fn accumulate(gradient: &mut [f32; 4], jacobian: &[[f32; 4]; 3], error: &[f32; 3]) {
+    for (row, error) in jacobian.iter().zip(error.iter()) {
+        for (value, derivative) in gradient.iter_mut().zip(row.iter()) {
+            *value += derivative * error;
+        }
+    }
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// Scale a vector to a length of 1. Returns `None` for zero or NaN vectors.
+fn normalize(vector: [f32; 3]) -> Option<[f32; 3]> {
+    let norm = (vector[0] * vector[0] + vector[1] * vector[1] + vector[2] * vector[2]).sqrt();
+    if norm > f32::EPSILON && norm.is_finite() {
+        Some([vector[0] / norm, vector[1] / norm, vector[2] / norm])
+    } else {
+        None
+    }
+}
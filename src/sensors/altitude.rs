@@ -0,0 +1,117 @@
+//! Host-side barometric altitude.
+//!
+//! The MCU's own `Pressure::altitude` uses a fixed reference that cannot be changed. `Altimeter` computes the
+//! altitude from the pressure and temperature instead, relative to a configurable sea-level pressure (QNH), and
+//! can track how much the altitude changed since a zero point, such as the ground floor of a building.
+//!
+//! ```
+//! use matrix_rhal::sensors::altitude::Altimeter;
+//! use matrix_rhal::sensors::units::{Celsius, Meters, Pascals};
+//! use matrix_rhal::sensors::Pressure;
+//!
+//! let ground = Pressure {
+//!     pressure: Pascals(100_000.0),
+//!     temperature: Celsius(20.0),
+//!     ..Default::default()
+//! };
+//!
+//! let mut altimeter = Altimeter::new();
+//! altimeter.set_reference_altitude(&ground, Meters(250.0));
+//! altimeter.set_zero(&ground);
+//!
+//! // 41 Pa less, about one floor up
+//! let upstairs = Pressure {
+//!     pressure: Pascals(99_959.0),
+//!     ..ground
+//! };
+//! assert!((altimeter.altitude(&upstairs).0 - 253.5).abs() < 0.1);
+//! assert!((altimeter.relative_altitude(&upstairs).unwrap().0 - 3.5).abs() < 0.1);
+//! ```
+use super::units::{Meters, Pascals};
+use super::Pressure;
+
+/// Temperature lapse rate of the standard atmosphere, in K/m.
+const LAPSE_RATE: f32 = 0.0065;
+/// Exponent of the barometric formula, `g * M / (R * L)`.
+const EXPONENT: f32 = 5.255_88;
+/// Specific gas constant of dry air divided by standard gravity, in m/K.
+const SCALE_HEIGHT_PER_KELVIN: f32 = 287.053 / 9.806_65;
+
+/// Computes altitudes from pressure readings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Altimeter {
+    sea_level: Pascals,
+    /// Pressure reading at the zero point of `relative_altitude`.
+    zero: Option<Pascals>,
+}
+
+impl Default for Altimeter {
+    fn default() -> Self {
+        Altimeter::new()
+    }
+}
+
+impl Altimeter {
+    /// Return an instance of Altimeter using the standard sea-level pressure and no zero point.
+    pub fn new() -> Altimeter {
+        Altimeter::with_sea_level(Pascals::SEA_LEVEL)
+    }
+
+    /// Return an instance of Altimeter using the given sea-level pressure (QNH), as published by weather services.
+    pub fn with_sea_level(sea_level: Pascals) -> Altimeter {
+        Altimeter {
+            sea_level,
+            zero: None,
+        }
+    }
+
+    pub fn sea_level(&self) -> Pascals {
+        self.sea_level
+    }
+
+    pub fn set_sea_level(&mut self, sea_level: Pascals) {
+        self.sea_level = sea_level;
+    }
+
+    /// Derive the sea-level pressure from a reading taken at a known altitude.
+    pub fn set_reference_altitude(&mut self, reading: &Pressure, altitude: Meters) {
+        let temperature = reading.temperature.kelvin() + LAPSE_RATE * altitude.0;
+        self.sea_level = Pascals(
+            reading.pressure.0 * (1.0 - LAPSE_RATE * altitude.0 / temperature).powf(-EXPONENT),
+        );
+    }
+
+    /// Altitude above sea level of a reading.
+    ///
+    /// The temperature of the reading is used as the air temperature at the sensor, which makes the result more
+    /// accurate than the standard atmosphere away from 15°C.
+    pub fn altitude(&self, reading: &Pressure) -> Meters {
+        let ratio = (self.sea_level.0 / reading.pressure.0).powf(1.0 / EXPONENT);
+        Meters((ratio - 1.0) * reading.temperature.kelvin() / LAPSE_RATE)
+    }
+
+    /// Use a reading as the zero point of `relative_altitude`.
+    pub fn set_zero(&mut self, reading: &Pressure) {
+        self.zero = Some(reading.pressure);
+    }
+
+    pub fn clear_zero(&mut self) {
+        self.zero = None;
+    }
+
+    /// Altitude of a reading above the zero point, or `None` if no zero point was set.
+    ///
+    /// This does not depend on the sea-level pressure, so it stays accurate to a fraction of a meter over short
+    /// periods even when the QNH is unknown. Changes in the weather slowly shift it, so the zero point should be set
+    /// again every few hours.
+    pub fn relative_altitude(&self, reading: &Pressure) -> Option<Meters> {
+        // hypsometric equation, the air column is assumed to be at the reading's temperature
+        self.zero.map(|zero| {
+            Meters(
+                SCALE_HEIGHT_PER_KELVIN
+                    * reading.temperature.kelvin()
+                    * (zero.0 / reading.pressure.0).ln(),
+            )
+        })
+    }
+}
@@ -0,0 +1,225 @@
+//! Gyroscope bias and accelerometer calibration.
+//!
+//! A gyroscope at rest should read zero, so its bias is the average of readings taken while the board is perfectly
+//! still. The accelerometer is calibrated by resting the board on each of its six faces in turn: each axis then
+//! reads +1g and -1g once, which gives its offset and scale.
+use super::invalid;
+use crate::sensors::units::{DegreesPerSecond, GForce};
+use crate::sensors::Imu;
+use crate::Error;
+
+/// Largest standard deviation of the gyroscope, in degrees per second, still considered as holding still.
+pub const MAX_GYRO_DEVIATION: f32 = 1.0;
+
+/// Largest standard deviation of the accelerometer, in g, still considered as holding still.
+pub const MAX_ACCEL_DEVIATION: f32 = 0.02;
+
+/// Estimate the gyroscope bias, in degrees per second, from readings taken while the board was still.
+///
+/// Fails if the readings vary by more than `MAX_GYRO_DEVIATION`, which means the board moved.
+pub fn estimate_gyro_bias(readings: &[Imu]) -> Result<[f32; 3], Error> {
+    let gyro: Vec<[f32; 3]> = readings
+        .iter()
+        .map(|imu| [imu.gyro_x.0, imu.gyro_y.0, imu.gyro_z.0])
+        .collect();
+
+    let (mean, deviation) = statistics(&gyro)?;
+    if deviation
+        .iter()
+        .any(|deviation| *deviation > MAX_GYRO_DEVIATION)
+    {
+        return Err(invalid(
+            "the board moved while estimating the gyroscope bias",
+        ));
+    }
+
+    Ok(mean)
+}
+
+/// Remove the gyroscope bias from an IMU reading.
+pub fn apply_gyro_bias(bias: [f32; 3], imu: &mut Imu) {
+    imu.gyro_x = DegreesPerSecond(imu.gyro_x.0 - bias[0]);
+    imu.gyro_y = DegreesPerSecond(imu.gyro_y.0 - bias[1]);
+    imu.gyro_z = DegreesPerSecond(imu.gyro_z.0 - bias[2]);
+}
+
+/// Correction applied to accelerometer readings: `(reading - offset) * scale`, per axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AccelCalibration {
+    /// Reading of each axis when it is level, in g.
+    pub offset: [f32; 3],
+    pub scale: [f32; 3],
+}
+
+impl Default for AccelCalibration {
+    /// A calibration that leaves readings untouched.
+    fn default() -> Self {
+        AccelCalibration {
+            offset: [0.0; 3],
+            scale: [1.0; 3],
+        }
+    }
+}
+
+impl AccelCalibration {
+    /// Correct the accelerometer fields of an IMU reading.
+    pub fn apply(&self, imu: &mut Imu) {
+        imu.accel_x = GForce((imu.accel_x.0 - self.offset[0]) * self.scale[0]);
+        imu.accel_y = GForce((imu.accel_y.0 - self.offset[1]) * self.scale[1]);
+        imu.accel_z = GForce((imu.accel_z.0 - self.offset[2]) * self.scale[2]);
+    }
+}
+
+/// Which face of the board points up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    XUp,
+    XDown,
+    YUp,
+    YDown,
+    ZUp,
+    ZDown,
+}
+
+impl Position {
+    /// Every position, in the order they are stored.
+    pub const ALL: [Position; 6] = [
+        Position::XUp,
+        Position::XDown,
+        Position::YUp,
+        Position::YDown,
+        Position::ZUp,
+        Position::ZDown,
+    ];
+
+    fn index(self) -> usize {
+        Position::ALL
+            .iter()
+            .position(|position| *position == self)
+            .unwrap_or(0)
+    }
+}
+
+/// Collects the six resting positions needed to calibrate the accelerometer.
+///
+/// # Example
+/// ```
+/// use matrix_rhal::sensors::calibration::AccelCalibrator;
+///
+/// let mut calibrator = AccelCalibrator::new();
+///
+/// // Z reads 0.1g too high and X is 2% too sensitive
+/// for reading in &[
+///     [1.02, 0.0, 0.1],
+///     [-1.02, 0.0, 0.1],
+///     [0.0, 1.0, 0.1],
+///     [0.0, -1.0, 0.1],
+///     [0.0, 0.0, 1.1],
+///     [0.0, 0.0, -0.9],
+/// ] {
+///     calibrator.add_position(*reading).unwrap();
+/// }
+///
+/// let calibration = calibrator.fit().unwrap();
+/// assert!((calibration.offset[2] - 0.1).abs() < 1e-6);
+/// assert!((calibration.scale[0] * 1.02 - 1.0).abs() < 1e-6);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AccelCalibrator {
+    /// Average reading of each position, in the order of `Position::ALL`.
+    positions: [Option<[f32; 3]>; 6],
+}
+
+impl AccelCalibrator {
+    /// Return an instance of AccelCalibrator with no positions recorded.
+    pub fn new() -> AccelCalibrator {
+        AccelCalibrator::default()
+    }
+
+    /// Record the average accelerometer reading, in g, of a resting position. The position is detected from the axis
+    /// closest to gravity, and recording the same position again replaces it.
+    pub fn add_position(&mut self, reading: [f32; 3]) -> Result<Position, Error> {
+        let (axis, value) = reading
+            .iter()
+            .copied()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()))
+            .ok_or_else(|| invalid("empty accelerometer reading"))?;
+
+        // an uncalibrated axis should still be within a few percent of 1g
+        if !(0.8..=1.2).contains(&value.abs()) {
+            return Err(invalid("the board is not resting on one of its faces"));
+        }
+
+        let position = Position::ALL[axis * 2 + if value > 0.0 { 0 } else { 1 }];
+        self.positions[position.index()] = Some(reading);
+        Ok(position)
+    }
+
+    /// Record a resting position from IMU readings taken while the board was still.
+    pub fn add_readings(&mut self, readings: &[Imu]) -> Result<Position, Error> {
+        let accel: Vec<[f32; 3]> = readings
+            .iter()
+            .map(|imu| [imu.accel_x.0, imu.accel_y.0, imu.accel_z.0])
+            .collect();
+
+        let (mean, deviation) = statistics(&accel)?;
+        if deviation
+            .iter()
+            .any(|deviation| *deviation > MAX_ACCEL_DEVIATION)
+        {
+            return Err(invalid("the board moved while recording a position"));
+        }
+
+        self.add_position(mean)
+    }
+
+    /// Positions that still need to be recorded.
+    pub fn missing(&self) -> Vec<Position> {
+        Position::ALL
+            .iter()
+            .copied()
+            .filter(|position| self.positions[position.index()].is_none())
+            .collect()
+    }
+
+    /// Compute the calibration once every position has been recorded.
+    pub fn fit(&self) -> Result<AccelCalibration, Error> {
+        let mut calibration = AccelCalibration::default();
+
+        for axis in 0..3 {
+            let (up, down) = match (self.positions[axis * 2], self.positions[axis * 2 + 1]) {
+                (Some(up), Some(down)) => (up[axis], down[axis]),
+                _ => return Err(invalid("not every position has been recorded")),
+            };
+
+            calibration.offset[axis] = (up + down) / 2.0;
+            calibration.scale[axis] = 2.0 / (up - down);
+        }
+
+        Ok(calibration)
+    }
+}
+
+/// Per-axis mean and standard deviation.
+fn statistics(readings: &[[f32; 3]]) -> Result<([f32; 3], [f32; 3]), Error> {
+    if readings.is_empty() {
+        return Err(invalid("no readings were collected"));
+    }
+
+    let count = readings.len() as f32;
+    let mut mean = [0.0; 3];
+    let mut deviation = [0.0; 3];
+
+    for axis in 0..3 {
+        mean[axis] = readings.iter().map(|reading| reading[axis]).sum::<f32>() / count;
+        let variance = readings
+            .iter()
+            .map(|reading| (reading[axis] - mean[axis]).powi(2))
+            .sum::<f32>()
+            / count;
+        deviation[axis] = variance.sqrt();
+    }
+
+    Ok((mean, deviation))
+}
@@ -0,0 +1,320 @@
+//! Magnetometer hard-iron and soft-iron calibration.
+//!
+//! Metal and magnets near the board distort the magnetic field it measures. Hard-iron distortion shifts every
+//! reading by a constant offset, while soft-iron distortion stretches the sphere of readings into an ellipsoid. Both
+//! are estimated by collecting readings while the board is rotated in every direction and fitting an ellipsoid to
+//! them.
+use super::{invalid, ImuCalibration};
+use crate::sensors::units::Gauss;
+use crate::sensors::Imu;
+use crate::Error;
+use std::path::Path;
+
+/// Where magnetometer calibrations were saved before each board got its own calibration profile.
+///
+/// `Sensors` still reads this file when the board has no profile yet. Saving the calibration moves it into the
+/// profile.
+pub const DEFAULT_MAG_CALIBRATION_PATH: &str = "/etc/matrix-rhal/magnetometer.toml";
+
+/// Correction applied to magnetometer readings: `soft_iron * (reading - hard_iron)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MagCalibration {
+    /// Constant offset of every reading, in gauss.
+    pub hard_iron: [f32; 3],
+    /// Row-major matrix turning the ellipsoid of readings back into a sphere.
+    pub soft_iron: [[f32; 3]; 3],
+}
+
+impl Default for MagCalibration {
+    /// A calibration that leaves readings untouched.
+    fn default() -> Self {
+        MagCalibration {
+            hard_iron: [0.0; 3],
+            soft_iron: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        }
+    }
+}
+
+impl MagCalibration {
+    /// Correct a single `[x, y, z]` reading.
+    pub fn correct(&self, reading: [f32; 3]) -> [f32; 3] {
+        let centered = [
+            reading[0] - self.hard_iron[0],
+            reading[1] - self.hard_iron[1],
+            reading[2] - self.hard_iron[2],
+        ];
+
+        let mut corrected = [0.0; 3];
+        for (row, value) in self.soft_iron.iter().zip(corrected.iter_mut()) {
+            *value = row[0] * centered[0] + row[1] * centered[1] + row[2] * centered[2];
+        }
+
+        corrected
+    }
+
+    /// Correct the magnetometer fields of an IMU reading.
+    pub fn apply(&self, imu: &mut Imu) {
+        let [x, y, z] = self.correct([imu.mag_x.0, imu.mag_y.0, imu.mag_z.0]);
+        imu.mag_x = Gauss(x);
+        imu.mag_y = Gauss(y);
+        imu.mag_z = Gauss(z);
+    }
+
+    /// Fit a calibration to `[x, y, z]` readings taken while the board was rotated in every direction.
+    ///
+    /// The corrected readings end up on a sphere whose radius is the average strength of the measured field.
+    ///
+    /// # Example
+    /// ```
+    /// use matrix_rhal::sensors::calibration::MagCalibration;
+    ///
+    /// // a 0.5 gauss field, stretched along X and shifted by (0.2, -0.1, 0.3)
+    /// let mut readings = Vec::new();
+    /// for i in 0..12 {
+    ///     for j in 1..6 {
+    ///         let (azimuth, polar) = (i as f32 * 0.52, j as f32 * 0.52);
+    ///         readings.push([
+    ///             0.2 + 0.6 * polar.sin() * azimuth.cos(),
+    ///             -0.1 + 0.5 * polar.sin() * azimuth.sin(),
+    ///             0.3 + 0.5 * polar.cos(),
+    ///         ]);
+    ///     }
+    /// }
+    ///
+    /// let calibration = MagCalibration::fit(&readings).unwrap();
+    /// assert!((calibration.hard_iron[0] - 0.2).abs() < 0.01);
+    /// ```
+    pub fn fit(readings: &[[f32; 3]]) -> Result<MagCalibration, Error> {
+        if readings.len() < 9 {
+            return Err(invalid("at least 9 readings are needed"));
+        }
+
+        // least squares fit of a x² + b y² + c z² + 2d xy + 2e xz + 2f yz + 2g x + 2h y + 2i z = 1
+        let mut normal = [[0.0f64; 10]; 9];
+        for reading in readings {
+            let [x, y, z] = [reading[0] as f64, reading[1] as f64, reading[2] as f64];
+            let terms = [
+                x * x,
+                y * y,
+                z * z,
+                2.0 * x * y,
+                2.0 * x * z,
+                2.0 * y * z,
+                2.0 * x,
+                2.0 * y,
+                2.0 * z,
+            ];
+
+            for (row, term) in normal.iter_mut().zip(terms.iter()) {
+                for (column, other) in terms.iter().enumerate() {
+                    row[column] += term * other;
+                }
+                row[9] += term;
+            }
+        }
+
+        let [a, b, c, d, e, f, g, h, i] = solve(normal)
+            .ok_or_else(|| invalid("the readings do not cover enough orientations"))?;
+        let shape = [[a, d, e], [d, b, f], [e, f, c]];
+        let linear = [g, h, i];
+
+        // center of the ellipsoid: -shape⁻¹ · linear
+        let mut system = [[0.0; 4]; 3];
+        for row in 0..3 {
+            system[row][..3].copy_from_slice(&shape[row]);
+            system[row][3] = -linear[row];
+        }
+        let center = solve(system).ok_or_else(|| invalid("the readings are degenerate"))?;
+
+        // (p - center)ᵀ · shape · (p - center) = scale
+        let scale = 1.0 - (0..3).map(|row| center[row] * linear[row]).sum::<f64>();
+        let (values, vectors) = symmetric_eigen(shape);
+        if scale <= 0.0 || values.iter().any(|value| *value <= 0.0) {
+            return Err(invalid("the readings do not form an ellipsoid"));
+        }
+
+        // radii of the ellipsoid are sqrt(scale / value); keep their geometric mean as the field strength
+        let radius = values
+            .iter()
+            .map(|value| (scale / value).sqrt())
+            .product::<f64>()
+            .cbrt();
+
+        // soft_iron = radius · V · diag(sqrt(value / scale)) · Vᵀ
+        let mut soft_iron = [[0.0f32; 3]; 3];
+        for (row, output) in soft_iron.iter_mut().enumerate() {
+            for (column, value) in output.iter_mut().enumerate() {
+                *value = (0..3)
+                    .map(|k| vectors[row][k] * (values[k] / scale).sqrt() * vectors[column][k])
+                    .sum::<f64>() as f32
+                    * radius as f32;
+            }
+        }
+
+        Ok(MagCalibration {
+            hard_iron: [center[0] as f32, center[1] as f32, center[2] as f32],
+            soft_iron,
+        })
+    }
+
+    /// Load a calibration saved with `save`, or the magnetometer part of a calibration profile.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<MagCalibration, Error> {
+        ImuCalibration::load(path)?
+            .mag
+            .ok_or_else(|| invalid("no magnetometer calibration was saved"))
+    }
+
+    /// Save the calibration on its own, in the same format as a calibration profile.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        ImuCalibration {
+            mag: Some(*self),
+            ..Default::default()
+        }
+        .save(path)
+    }
+}
+
+/// Collects magnetometer readings while the board is being rotated.
+#[derive(Debug, Clone, Default)]
+pub struct MagCalibrator {
+    readings: Vec<[f32; 3]>,
+}
+
+impl MagCalibrator {
+    /// Number of direction bins used by `coverage`: 8 around the Z axis times 4 from pole to pole.
+    const BINS: usize = 32;
+
+    /// Return an instance of MagCalibrator with no readings.
+    pub fn new() -> MagCalibrator {
+        MagCalibrator::default()
+    }
+
+    /// Add an uncalibrated IMU reading. Readings with NaN values are ignored.
+    pub fn add(&mut self, imu: &Imu) {
+        let reading = [imu.mag_x.0, imu.mag_y.0, imu.mag_z.0];
+        if reading.iter().all(|value| value.is_finite()) {
+            self.readings.push(reading);
+        }
+    }
+
+    /// Readings collected so far.
+    pub fn readings(&self) -> &[[f32; 3]] {
+        &self.readings
+    }
+
+    /// Rough fraction (`0.0..=1.0`) of directions covered so far. Aim for at least `0.8` before fitting.
+    pub fn coverage(&self) -> f32 {
+        if self.readings.is_empty() {
+            return 0.0;
+        }
+
+        // the midpoint of each axis is a good enough center to bin directions
+        let mut center = [0.0; 3];
+        for (axis, value) in center.iter_mut().enumerate() {
+            let (min, max) = self
+                .readings
+                .iter()
+                .fold((f32::MAX, f32::MIN), |(min, max), reading| {
+                    (min.min(reading[axis]), max.max(reading[axis]))
+                });
+            *value = (min + max) / 2.0;
+        }
+
+        let mut bins = [false; MagCalibrator::BINS];
+        for reading in &self.readings {
+            let [x, y, z] = [
+                reading[0] - center[0],
+                reading[1] - center[1],
+                reading[2] - center[2],
+            ];
+            let length = (x * x + y * y + z * z).sqrt();
+            if length <= f32::EPSILON {
+                continue;
+            }
+
+            let azimuth = (y.atan2(x) + std::f32::consts::PI) / (2.0 * std::f32::consts::PI);
+            let polar = ((z / length).clamp(-1.0, 1.0) + 1.0) / 2.0;
+            let bin = (azimuth * 8.0).min(7.0) as usize * 4 + (polar * 4.0).min(3.0) as usize;
+            bins[bin] = true;
+        }
+
+        bins.iter().filter(|bin| **bin).count() as f32 / MagCalibrator::BINS as f32
+    }
+
+    /// Fit a calibration to the readings collected so far.
+    pub fn fit(&self) -> Result<MagCalibration, Error> {
+        MagCalibration::fit(&self.readings)
+    }
+}
+
+/// Solve a linear system given as an augmented `N x (N + 1)` matrix, using Gaussian elimination with partial
+/// pivoting. Returns `None` if the system is singular.
+fn solve<const N: usize, const M: usize>(mut matrix: [[f64; M]; N]) -> Option<[f64; N]> {
+    for column in 0..N {
+        let pivot = (column..N).max_by(|a, b| {
+            matrix[*a][column]
+                .abs()
+                .total_cmp(&matrix[*b][column].abs())
+        })?;
+        if matrix[pivot][column].abs() < 1e-12 {
+            return None;
+        }
+        matrix.swap(column, pivot);
+
+        let pivot_row = matrix[column];
+        for row in matrix.iter_mut().skip(column + 1) {
+            let factor = row[column] / pivot_row[column];
+            for (value, pivot) in row[column..].iter_mut().zip(&pivot_row[column..]) {
+                *value -= factor * pivot;
+            }
+        }
+    }
+
+    let mut solution = [0.0; N];
+    for row in (0..N).rev() {
+        let known: f64 = (row + 1..N).map(|k| matrix[row][k] * solution[k]).sum();
+        solution[row] = (matrix[row][N] - known) / matrix[row][row];
+    }
+
+    Some(solution)
+}
+
+/// Eigenvalues and eigenvectors (as columns) of a symmetric 3x3 matrix, using Jacobi rotations.
+fn symmetric_eigen(mut matrix: [[f64; 3]; 3]) -> ([f64; 3], [[f64; 3]; 3]) {
+    let mut vectors = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+    for _ in 0..50 {
+        // largest off-diagonal element
+        let (p, q) = [(0, 1), (0, 2), (1, 2)]
+            .iter()
+            .copied()
+            .max_by(|(a, b), (c, d)| matrix[*a][*b].abs().total_cmp(&matrix[*c][*d].abs()))
+            .unwrap_or((0, 1));
+        if matrix[p][q].abs() < 1e-15 {
+            break;
+        }
+
+        let theta = (matrix[q][q] - matrix[p][p]) / (2.0 * matrix[p][q]);
+        let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+        let (cos, sin) = (1.0 / (t * t + 1.0).sqrt(), t / (t * t + 1.0).sqrt());
+
+        // matrix = Jᵀ · matrix · J
+        for row in matrix.iter_mut() {
+            let (kp, kq) = (row[p], row[q]);
+            row[p] = cos * kp - sin * kq;
+            row[q] = sin * kp + cos * kq;
+        }
+        let (row_p, row_q) = (matrix[p], matrix[q]);
+        for (k, (pk, qk)) in row_p.iter().zip(row_q.iter()).enumerate() {
+            matrix[p][k] = cos * pk - sin * qk;
+            matrix[q][k] = sin * pk + cos * qk;
+        }
+        for row in vectors.iter_mut() {
+            let (vp, vq) = (row[p], row[q]);
+            row[p] = cos * vp - sin * vq;
+            row[q] = sin * vp + cos * vq;
+        }
+    }
+
+    ([matrix[0][0], matrix[1][1], matrix[2][2]], vectors)
+}
@@ -0,0 +1,232 @@
+//! Sensor calibration.
+//!
+//! The board's self-heating is compensated by the `temperature` module.
+//!
+//! Each board gets a calibration profile, stored as a small TOML compatible file named after the board's type
+//! and version in `DEFAULT_PROFILE_DIRECTORY`. `Sensors` loads the profile of the current board when it is
+//! created and applies it to every IMU reading. Every entry is optional, and `#` starts a comment:
+//!
+//! ```toml
+//! # magnetometer, see `MagCalibration`
+//! hard_iron = [0.12, -0.3, 0.05]
+//! soft_iron = [1.02, 0.01, 0.0, 0.01, 0.97, 0.0, 0.0, 0.0, 1.01]
+//! # gyroscope, in degrees per second
+//! gyro_bias = [0.8, -1.2, 0.3] # measured at 25°C
+//! # accelerometer, see `AccelCalibration`
+//! accel_offset = [0.01, -0.02, 0.05]
+//! accel_scale = [0.99, 1.0, 1.01]
+//! ```
+use super::Imu;
+use crate::{Bus, Error};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+pub mod inertial;
+pub mod magnetometer;
+pub mod temperature;
+pub use inertial::*;
+pub use magnetometer::*;
+pub use temperature::*;
+
+/// Where calibration profiles are stored.
+pub const DEFAULT_PROFILE_DIRECTORY: &str = "/etc/matrix-rhal/calibration";
+
+/// Every correction applied to the IMU readings of a board.
+///
+/// # Example
+/// ```
+/// use matrix_rhal::sensors::calibration::ImuCalibration;
+///
+/// let profile: ImuCalibration = "gyro_bias = [0.8, -1.2, 0.3] # measured at 25°C".parse().unwrap();
+/// assert_eq!(profile.gyro_bias, Some([0.8, -1.2, 0.3]));
+/// assert_eq!(profile.mag, None);
+///
+/// assert_eq!(profile.to_string().parse::<ImuCalibration>().unwrap(), profile);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ImuCalibration {
+    pub mag: Option<MagCalibration>,
+    /// Gyroscope reading at rest, in degrees per second.
+    pub gyro_bias: Option<[f32; 3]>,
+    pub accel: Option<AccelCalibration>,
+}
+
+impl ImuCalibration {
+    /// Name identifying the board's profile, such as `creator-00010002`.
+    pub fn profile_name(bus: &Bus) -> String {
+        format!("{:?}-{:08x}", bus.device_name, bus.device_version).to_lowercase()
+    }
+
+    /// Path of the board's profile in `DEFAULT_PROFILE_DIRECTORY`.
+    pub fn profile_path(bus: &Bus) -> PathBuf {
+        Path::new(DEFAULT_PROFILE_DIRECTORY)
+            .join(format!("{}.toml", ImuCalibration::profile_name(bus)))
+    }
+
+    /// Apply every correction to an IMU reading.
+    pub fn apply(&self, imu: &mut Imu) {
+        if let Some(mag) = &self.mag {
+            mag.apply(imu);
+        }
+        if let Some(bias) = self.gyro_bias {
+            apply_gyro_bias(bias, imu);
+        }
+        if let Some(accel) = &self.accel {
+            accel.apply(imu);
+        }
+    }
+
+    /// Load the profile of a board. A board without a profile falls back to the magnetometer calibration saved in
+    /// `DEFAULT_MAG_CALIBRATION_PATH`, if any, and otherwise to no calibration at all.
+    ///
+    /// Fails if a file exists but cannot be read or parsed.
+    pub fn load_profile(bus: &Bus) -> Result<ImuCalibration, Error> {
+        for path in &[
+            ImuCalibration::profile_path(bus),
+            PathBuf::from(DEFAULT_MAG_CALIBRATION_PATH),
+        ] {
+            if let Some(source) = read_if_exists(path)? {
+                return source.parse();
+            }
+        }
+
+        Ok(ImuCalibration::default())
+    }
+
+    /// Load a profile saved with `save`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ImuCalibration, Error> {
+        let source = std::fs::read_to_string(path).map_err(|error| Error::Any(Box::new(error)))?;
+        source.parse()
+    }
+
+    /// Save the profile, creating its directory if needed.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        write_creating_directory(path.as_ref(), &self.to_string())
+    }
+}
+
+impl fmt::Display for ImuCalibration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(mag) = &self.mag {
+            writeln!(f, "hard_iron = {}", list(&mag.hard_iron))?;
+            writeln!(f, "soft_iron = {}", list(&mag.soft_iron.concat()))?;
+        }
+        if let Some(bias) = &self.gyro_bias {
+            writeln!(f, "gyro_bias = {}", list(bias))?;
+        }
+        if let Some(accel) = &self.accel {
+            writeln!(f, "accel_offset = {}", list(&accel.offset))?;
+            writeln!(f, "accel_scale = {}", list(&accel.scale))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for ImuCalibration {
+    type Err = Error;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let entries = parse_entries(source, |key| match key {
+            "soft_iron" => Some(9),
+            "hard_iron" | "gyro_bias" | "accel_offset" | "accel_scale" => Some(3),
+            _ => None,
+        })?;
+
+        let vector = |values: &[f32]| [values[0], values[1], values[2]];
+        let pair = |first: &str, second: &str| match (entries.get(first), entries.get(second)) {
+            (Some(first), Some(second)) => Ok(Some((first, second))),
+            (None, None) => Ok(None),
+            _ => Err(invalid(&format!(
+                "`{}` and `{}` go together",
+                first, second
+            ))),
+        };
+
+        Ok(ImuCalibration {
+            mag: pair("hard_iron", "soft_iron")?.map(|(hard_iron, soft_iron)| MagCalibration {
+                hard_iron: vector(hard_iron),
+                soft_iron: [
+                    vector(&soft_iron[0..3]),
+                    vector(&soft_iron[3..6]),
+                    vector(&soft_iron[6..9]),
+                ],
+            }),
+            gyro_bias: entries.get("gyro_bias").map(|bias| vector(bias)),
+            accel: pair("accel_offset", "accel_scale")?.map(|(offset, scale)| AccelCalibration {
+                offset: vector(offset),
+                scale: vector(scale),
+            }),
+        })
+    }
+}
+
+pub(crate) fn invalid(message: &str) -> Error {
+    Error::InvalidCalibration(message.to_string())
+}
+
+/// Parse the `key = [values]` lines of a profile. `expected` returns how many values a key takes, or `None` for
+/// unknown keys.
+pub(crate) fn parse_entries(
+    source: &str,
+    expected: fn(&str) -> Option<usize>,
+) -> Result<HashMap<&str, Vec<f32>>, Error> {
+    let mut entries = HashMap::new();
+
+    for line in source.lines() {
+        // nothing in a profile can contain a `#`, so it always starts a comment
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| invalid(&format!("expected `key = [values]`, found \"{}\"", line)))?;
+        let key = key.trim();
+        let values = value
+            .trim()
+            .strip_prefix('[')
+            .and_then(|value| value.strip_suffix(']'))
+            .ok_or_else(|| invalid(&format!("expected a list of numbers for `{}`", key)))?
+            .split(',')
+            .map(|number| number.trim().parse::<f32>())
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|error| invalid(&format!("`{}`: {}", key, error)))?;
+
+        let expected = expected(key).ok_or_else(|| invalid(&format!("unexpected `{}`", key)))?;
+        if values.len() != expected {
+            return Err(invalid(&format!("`{}` needs {} values", key, expected)));
+        }
+
+        entries.insert(key, values);
+    }
+
+    Ok(entries)
+}
+
+/// Format values as a profile list, such as `[1.0, -0.5]`.
+pub(crate) fn list(values: &[f32]) -> String {
+    let values: Vec<String> = values.iter().map(|value| format!("{:?}", value)).collect();
+    format!("[{}]", values.join(", "))
+}
+
+/// Read a file, or return `None` if it doesn't exist.
+pub(crate) fn read_if_exists(path: &Path) -> Result<Option<String>, Error> {
+    match std::fs::read_to_string(path) {
+        Ok(source) => Ok(Some(source)),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(Error::Any(Box::new(error))),
+    }
+}
+
+/// Write a file, creating its directory if needed.
+pub(crate) fn write_creating_directory(path: &Path, contents: &str) -> Result<(), Error> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory).map_err(|error| Error::Any(Box::new(error)))?;
+    }
+
+    std::fs::write(path, contents).map_err(|error| Error::Any(Box::new(error)))
+}
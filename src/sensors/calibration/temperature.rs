@@ -0,0 +1,265 @@
+//! Compensation of the board's self-heating.
+//!
+//! The humidity and pressure sensors sit next to the Raspberry Pi and the FPGA, so they read warmer than the
+//! surrounding air and the relative humidity reads correspondingly low. Each sensor's error is modeled as a fixed
+//! offset plus a fraction of how much hotter the CPU is than the sensor:
+//!
+//! `ambient = measured - offset - cpu_factor * (cpu - measured)`
+//!
+//! The model can be configured by hand or learned from readings taken next to a reference thermometer. Each board
+//! keeps its model in a file next to its IMU calibration profile, where every entry is `[offset, cpu_factor]`:
+//!
+//! ```toml
+//! humidity = [3.0, 0.1]
+//! pressure = [2.5, 0.08]
+//! ```
+//!
+//! ```
+//! use matrix_rhal::sensors::calibration::{SelfHeatingCompensation, TemperatureCalibrator};
+//! use matrix_rhal::sensors::units::{Celsius, Percent};
+//! use matrix_rhal::sensors::Humidity;
+//!
+//! // the sensor reads 3°C high, plus a tenth of the CPU's lead
+//! let mut calibrator = TemperatureCalibrator::new();
+//! calibrator.add(Celsius(25.0), Some(Celsius(45.0)), Celsius(20.0));
+//! calibrator.add(Celsius(27.0), Some(Celsius(57.0)), Celsius(21.0));
+//!
+//! let compensation = calibrator.fit().unwrap();
+//! assert!((compensation.offset - 3.0).abs() < 1e-3);
+//! assert!((compensation.cpu_factor - 0.1).abs() < 1e-3);
+//!
+//! // the air holds the same water, so it is more humid relative to the cooler temperature
+//! let mut humidity = Humidity {
+//!     humidity: Percent(40.0),
+//!     temperature: Celsius(25.0),
+//! };
+//! compensation.apply_humidity(&mut humidity, Some(Celsius(45.0)));
+//! assert!((humidity.temperature.0 - 20.0).abs() < 1e-3);
+//! assert!(humidity.humidity.0 > 53.0);
+//!
+//! let model = SelfHeatingCompensation {
+//!     humidity: compensation,
+//!     ..Default::default()
+//! };
+//! assert_eq!(model.to_string().parse::<SelfHeatingCompensation>().unwrap(), model);
+//! ```
+use super::{invalid, list, parse_entries, read_if_exists, write_creating_directory};
+use super::{ImuCalibration, DEFAULT_PROFILE_DIRECTORY};
+use crate::sensors::environment::saturation_vapor_pressure;
+use crate::sensors::units::{Celsius, Percent};
+use crate::sensors::{Humidity, Pressure};
+use crate::{Bus, Error};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Where the Raspberry Pi reports its CPU temperature, in thousandths of a degree.
+pub const CPU_TEMPERATURE_PATH: &str = "/sys/class/thermal/thermal_zone0/temp";
+
+/// Read the Raspberry Pi's CPU temperature from `CPU_TEMPERATURE_PATH`.
+pub fn read_cpu_temperature() -> Result<Celsius, Error> {
+    let source = std::fs::read_to_string(CPU_TEMPERATURE_PATH)
+        .map_err(|error| Error::Any(Box::new(error)))?;
+    let millidegrees = source
+        .trim()
+        .parse::<f32>()
+        .map_err(|error| Error::Any(Box::new(error)))?;
+
+    Ok(Celsius(millidegrees / 1000.0))
+}
+
+/// Self-heating model of one sensor. The default leaves readings untouched.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TemperatureCompensation {
+    /// How much warmer than the air the sensor reads, in °C.
+    pub offset: f32,
+    /// Fraction of the difference between the CPU and sensor temperatures that leaks into the sensor.
+    pub cpu_factor: f32,
+}
+
+impl TemperatureCompensation {
+    /// Estimate the air temperature. The CPU term is left out if its temperature is unknown.
+    pub fn correct(&self, measured: Celsius, cpu: Option<Celsius>) -> Celsius {
+        let heating = match cpu {
+            Some(cpu) => self.offset + self.cpu_factor * (cpu.0 - measured.0),
+            None => self.offset,
+        };
+
+        Celsius(measured.0 - heating)
+    }
+
+    /// Correct the temperature of a humidity reading and recompute its relative humidity.
+    ///
+    /// The amount of water in the air doesn't change, only the temperature it is relative to.
+    pub fn apply_humidity(&self, humidity: &mut Humidity, cpu: Option<Celsius>) {
+        let measured = humidity.temperature;
+        humidity.temperature = self.correct(measured, cpu);
+
+        let ratio = saturation_vapor_pressure(measured).0
+            / saturation_vapor_pressure(humidity.temperature).0;
+        humidity.humidity = Percent((humidity.humidity.0 * ratio).min(100.0));
+    }
+
+    /// Correct the temperature of a pressure reading.
+    pub fn apply_pressure(&self, pressure: &mut Pressure, cpu: Option<Celsius>) {
+        pressure.temperature = self.correct(pressure.temperature, cpu);
+    }
+}
+
+/// Self-heating models of both sensors that measure temperature.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SelfHeatingCompensation {
+    pub humidity: TemperatureCompensation,
+    pub pressure: TemperatureCompensation,
+}
+
+impl SelfHeatingCompensation {
+    /// Whether either model needs the CPU temperature.
+    pub fn uses_cpu_temperature(&self) -> bool {
+        self.humidity.cpu_factor != 0.0 || self.pressure.cpu_factor != 0.0
+    }
+
+    /// Path of the board's model in `DEFAULT_PROFILE_DIRECTORY`, such as `creator-00010002-temperature.toml`.
+    pub fn profile_path(bus: &Bus) -> PathBuf {
+        Path::new(DEFAULT_PROFILE_DIRECTORY).join(format!(
+            "{}-temperature.toml",
+            ImuCalibration::profile_name(bus)
+        ))
+    }
+
+    /// Load the model of a board, or no compensation at all if none was saved.
+    ///
+    /// Fails if the file exists but cannot be read or parsed.
+    pub fn load_profile(bus: &Bus) -> Result<SelfHeatingCompensation, Error> {
+        match read_if_exists(&SelfHeatingCompensation::profile_path(bus))? {
+            Some(source) => source.parse(),
+            None => Ok(SelfHeatingCompensation::default()),
+        }
+    }
+
+    /// Load a model saved with `save`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SelfHeatingCompensation, Error> {
+        let source = std::fs::read_to_string(path).map_err(|error| Error::Any(Box::new(error)))?;
+        source.parse()
+    }
+
+    /// Save the model, creating its directory if needed.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        write_creating_directory(path.as_ref(), &self.to_string())
+    }
+}
+
+impl fmt::Display for SelfHeatingCompensation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let model = |compensation: &TemperatureCompensation| {
+            list(&[compensation.offset, compensation.cpu_factor])
+        };
+
+        writeln!(f, "humidity = {}", model(&self.humidity))?;
+        writeln!(f, "pressure = {}", model(&self.pressure))
+    }
+}
+
+impl FromStr for SelfHeatingCompensation {
+    type Err = Error;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let entries = parse_entries(source, |key| match key {
+            "humidity" | "pressure" => Some(2),
+            _ => None,
+        })?;
+
+        // a missing sensor is left uncompensated
+        let model = |key: &str| {
+            entries
+                .get(key)
+                .map(|values| TemperatureCompensation {
+                    offset: values[0],
+                    cpu_factor: values[1],
+                })
+                .unwrap_or_default()
+        };
+
+        Ok(SelfHeatingCompensation {
+            humidity: model("humidity"),
+            pressure: model("pressure"),
+        })
+    }
+}
+
+/// Learns a `TemperatureCompensation` from readings taken next to a reference thermometer.
+///
+/// Readings should cover different CPU loads for the CPU factor to be meaningful.
+#[derive(Debug, Clone, Default)]
+pub struct TemperatureCalibrator {
+    /// Measured, CPU and reference temperatures.
+    samples: Vec<(f32, Option<f32>, f32)>,
+}
+
+impl TemperatureCalibrator {
+    /// Return an instance of TemperatureCalibrator with no samples.
+    pub fn new() -> TemperatureCalibrator {
+        TemperatureCalibrator::default()
+    }
+
+    /// Record a sensor reading, the CPU temperature at the time, if known, and what the reference thermometer read.
+    pub fn add(&mut self, measured: Celsius, cpu: Option<Celsius>, reference: Celsius) {
+        self.samples
+            .push((measured.0, cpu.map(|cpu| cpu.0), reference.0));
+    }
+
+    /// Fit the model with least squares. Only the offset is fitted if a CPU temperature is missing or never
+    /// changed relative to the sensor.
+    pub fn fit(&self) -> Result<TemperatureCompensation, Error> {
+        if self.samples.is_empty() {
+            return Err(invalid("no temperature samples were recorded"));
+        }
+
+        let count = self.samples.len() as f32;
+        // error of each sample and the CPU's lead over the sensor
+        let points: Option<Vec<(f32, f32)>> = self
+            .samples
+            .iter()
+            .map(|(measured, cpu, reference)| cpu.map(|cpu| (cpu - measured, measured - reference)))
+            .collect();
+        let mean_error = self
+            .samples
+            .iter()
+            .map(|(measured, _, reference)| measured - reference)
+            .sum::<f32>()
+            / count;
+
+        let points = match points {
+            Some(points) => points,
+            None => {
+                return Ok(TemperatureCompensation {
+                    offset: mean_error,
+                    cpu_factor: 0.0,
+                })
+            }
+        };
+
+        let mean_lead = points.iter().map(|(lead, _)| lead).sum::<f32>() / count;
+        let variance = points
+            .iter()
+            .map(|(lead, _)| (lead - mean_lead).powi(2))
+            .sum::<f32>();
+        if variance < 1e-3 {
+            return Ok(TemperatureCompensation {
+                offset: mean_error,
+                cpu_factor: 0.0,
+            });
+        }
+
+        let covariance = points
+            .iter()
+            .map(|(lead, error)| (lead - mean_lead) * (error - mean_error))
+            .sum::<f32>();
+        let cpu_factor = covariance / variance;
+
+        Ok(TemperatureCompensation {
+            offset: mean_error - cpu_factor * mean_lead,
+            cpu_factor,
+        })
+    }
+}
@@ -1,44 +1,289 @@
+use super::environment::Environment;
+use super::units::*;
+use crate::bus::memory_map::mcu_offset;
+use crate::Error;
+use std::ops::RangeInclusive;
+use std::time::Instant;
+
 /// Number of Bytes needed to represent UV data.
 pub const UV_BYTES: i32 = 4;
+/// Plausible UV index values.
+pub const UV_RANGE: RangeInclusive<f32> = 0.0..=20.0;
 
 /// Number of Bytes needed to represent Pressure data.
 pub const PRESSURE_BYTES: i32 = 12;
-#[derive(Debug, Default)]
+/// Pressure range of the MPL3115A2, in Pascals.
+pub const PRESSURE_RANGE: RangeInclusive<f32> = 20_000.0..=110_000.0;
+/// Plausible altitudes, in meters.
+pub const ALTITUDE_RANGE: RangeInclusive<f32> = -1_000.0..=12_000.0;
+/// Operating temperature range of the MPL3115A2, in Celsius.
+pub const PRESSURE_TEMPERATURE_RANGE: RangeInclusive<f32> = -40.0..=85.0;
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub struct Pressure {
-    pub pressure: f32,
-    pub altitude: f32,
-    pub temperature: f32,
+    pub pressure: Pascals,
+    pub altitude: Meters,
+    pub temperature: Celsius,
+}
+
+impl Pressure {
+    /// Decode the sensor's raw values.
+    pub(crate) fn from_raw(data: &[i32]) -> Pressure {
+        Pressure {
+            pressure: Pascals(from_fixed_point(data[1])),
+            altitude: Meters(from_fixed_point(data[0])),
+            temperature: Celsius(from_fixed_point(data[2])),
+        }
+    }
+
+    /// Check that every value is within what the sensor can physically report.
+    pub fn validate(&self) -> Result<(), Error> {
+        check_range("pressure", self.pressure.0, PRESSURE_RANGE)?;
+        check_range("altitude", self.altitude.0, ALTITUDE_RANGE)?;
+        check_range(
+            "pressure temperature",
+            self.temperature.0,
+            PRESSURE_TEMPERATURE_RANGE,
+        )
+    }
 }
 
 /// Number of Bytes needed to represent Humidity data.
 pub const HUMIDITY_BYTES: i32 = 8;
-#[derive(Debug, Default)]
+/// Relative humidity range, in percent.
+pub const HUMIDITY_RANGE: RangeInclusive<f32> = 0.0..=100.0;
+/// Operating temperature range of the HTS221, in Celsius.
+pub const HUMIDITY_TEMPERATURE_RANGE: RangeInclusive<f32> = -40.0..=120.0;
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub struct Humidity {
-    pub humidity: f32,
-    pub temperature: f32,
+    pub humidity: Percent,
+    pub temperature: Celsius,
+}
+
+impl Humidity {
+    /// Decode the sensor's raw values.
+    pub(crate) fn from_raw(data: &[i32]) -> Humidity {
+        Humidity {
+            humidity: Percent(from_fixed_point(data[0])),
+            temperature: Celsius(from_fixed_point(data[1])),
+        }
+    }
+
+    /// Check that every value is within what the sensor can physically report.
+    pub fn validate(&self) -> Result<(), Error> {
+        check_range("humidity", self.humidity.0, HUMIDITY_RANGE)?;
+        check_range(
+            "humidity temperature",
+            self.temperature.0,
+            HUMIDITY_TEMPERATURE_RANGE,
+        )
+    }
 }
 
 /// Number of Bytes needed to represent IMU data.
 pub const IMU_BYTES: i32 = 60;
-#[derive(Debug, Default)]
+/// Full scale of the LSM9DS1 accelerometer, in g.
+pub const ACCEL_RANGE: RangeInclusive<f32> = -16.0..=16.0;
+/// Full scale of the LSM9DS1 gyroscope, in degrees per second.
+pub const GYRO_RANGE: RangeInclusive<f32> = -2_000.0..=2_000.0;
+/// Full scale of the LSM9DS1 magnetometer, in gauss.
+pub const MAG_RANGE: RangeInclusive<f32> = -16.0..=16.0;
+/// Plausible yaw, pitch and roll values, in degrees.
+pub const ANGLE_RANGE: RangeInclusive<f32> = -360.0..=360.0;
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub struct Imu {
-    pub accel_x: f32,
-    pub accel_y: f32,
-    pub accel_z: f32,
+    pub accel_x: GForce,
+    pub accel_y: GForce,
+    pub accel_z: GForce,
 
-    pub gyro_x: f32,
-    pub gyro_y: f32,
-    pub gyro_z: f32,
+    pub gyro_x: DegreesPerSecond,
+    pub gyro_y: DegreesPerSecond,
+    pub gyro_z: DegreesPerSecond,
 
-    pub mag_x: f32,
-    pub mag_y: f32,
-    pub mag_z: f32,
+    pub mag_x: Gauss,
+    pub mag_y: Gauss,
+    pub mag_z: Gauss,
 
+    /// Raw magnetometer offsets reported by the MCU.
     pub mag_offset_x: f32,
     pub mag_offset_y: f32,
     pub mag_offset_z: f32,
 
-    pub yaw: f32,
-    pub pitch: f32,
-    pub roll: f32,
+    pub yaw: Degrees,
+    pub pitch: Degrees,
+    pub roll: Degrees,
+}
+
+impl Imu {
+    /// Decode the sensor's raw values.
+    pub(crate) fn from_raw(data: &[i32]) -> Imu {
+        Imu {
+            accel_x: GForce(from_fixed_point(data[0])),
+            accel_y: GForce(from_fixed_point(data[1])),
+            accel_z: GForce(from_fixed_point(data[2])),
+
+            gyro_x: DegreesPerSecond(from_fixed_point(data[3])),
+            gyro_y: DegreesPerSecond(from_fixed_point(data[4])),
+            gyro_z: DegreesPerSecond(from_fixed_point(data[5])),
+
+            mag_x: Gauss(from_fixed_point(data[6])),
+            mag_y: Gauss(from_fixed_point(data[7])),
+            mag_z: Gauss(from_fixed_point(data[8])),
+
+            // TODO: ask why we have these. They seem to be unused, see `sensors::calibration` instead.
+            mag_offset_x: data[9] as f32,
+            mag_offset_y: data[10] as f32,
+            mag_offset_z: data[11] as f32,
+
+            // These values are already floats so we just need to treat them as one.
+            yaw: Degrees(f32::from_bits(data[12] as u32)),
+            pitch: Degrees(f32::from_bits(data[13] as u32)),
+            roll: Degrees(f32::from_bits(data[14] as u32)),
+        }
+    }
+
+    /// Check that every value is within what the sensor can physically report and that no angle is NaN.
+    ///
+    /// # Example
+    /// ```
+    /// use matrix_rhal::sensors::units::{Degrees, GForce};
+    ///
+    /// let imu = matrix_rhal::sensors::Imu {
+    ///     accel_z: GForce(1.0),
+    ///     yaw: Degrees(f32::NAN),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// assert!(imu.validate().is_err());
+    /// ```
+    pub fn validate(&self) -> Result<(), Error> {
+        let values = [
+            ("accel_x", self.accel_x.0, ACCEL_RANGE),
+            ("accel_y", self.accel_y.0, ACCEL_RANGE),
+            ("accel_z", self.accel_z.0, ACCEL_RANGE),
+            ("gyro_x", self.gyro_x.0, GYRO_RANGE),
+            ("gyro_y", self.gyro_y.0, GYRO_RANGE),
+            ("gyro_z", self.gyro_z.0, GYRO_RANGE),
+            ("mag_x", self.mag_x.0, MAG_RANGE),
+            ("mag_y", self.mag_y.0, MAG_RANGE),
+            ("mag_z", self.mag_z.0, MAG_RANGE),
+            ("yaw", self.yaw.0, ANGLE_RANGE),
+            ("pitch", self.pitch.0, ANGLE_RANGE),
+            ("roll", self.roll.0, ANGLE_RANGE),
+        ];
+
+        for (name, value, range) in values.iter().cloned() {
+            check_range(name, value, range)?;
+        }
+
+        Ok(())
+    }
+
+    /// Return the direction of magnetic north, in degrees counter-clockwise from the board's X axis.
+    ///
+    /// The magnetometer reading is tilt compensated using the accelerometer, so the board does not need to be flat.
+    /// This assumes the accelerometer reads about +1g on the Z axis while the board lies flat.
+    pub fn heading(&self) -> f32 {
+        let (accel_x, accel_y, accel_z) = (self.accel_x.0, self.accel_y.0, self.accel_z.0);
+        let (mag_x, mag_y, mag_z) = (self.mag_x.0, self.mag_y.0, self.mag_z.0);
+
+        let roll = accel_y.atan2(accel_z);
+        let pitch = (-accel_x).atan2(accel_y * roll.sin() + accel_z * roll.cos());
+
+        // rotate the magnetic field back onto the horizontal plane
+        let horizontal_x = mag_x * pitch.cos()
+            + mag_y * pitch.sin() * roll.sin()
+            + mag_z * pitch.sin() * roll.cos();
+        let horizontal_y = mag_y * roll.cos() - mag_z * roll.sin();
+
+        let heading = horizontal_y.atan2(horizontal_x).to_degrees();
+        (heading + 360.0) % 360.0
+    }
+
+    /// Return the direction of the board's lowest edge, in degrees counter-clockwise from the board's X axis, and
+    /// how far the board is tilted from flat, in degrees.
+    ///
+    /// # Example
+    /// ```
+    /// use matrix_rhal::sensors::units::GForce;
+    ///
+    /// // X edge of the board tilted 30 degrees down
+    /// let imu = matrix_rhal::sensors::Imu {
+    ///     accel_x: GForce(-0.5),
+    ///     accel_z: GForce(0.866),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let (direction, tilt) = imu.tilt();
+    /// assert_eq!(direction.round(), 0.0);
+    /// assert_eq!(tilt.round(), 30.0);
+    /// ```
+    pub fn tilt(&self) -> (f32, f32) {
+        let (accel_x, accel_y, accel_z) = (self.accel_x.0, self.accel_y.0, self.accel_z.0);
+
+        let horizontal = accel_x.hypot(accel_y);
+        let direction = (-accel_y).atan2(-accel_x).to_degrees();
+        let magnitude = horizontal.atan2(accel_z).to_degrees();
+
+        ((direction + 360.0) % 360.0, magnitude)
+    }
+}
+
+/// Number of Bytes from the start of the UV data to the end of the IMU data, which the MCU stores contiguously.
+pub const ALL_SENSORS_BYTES: i32 = mcu_offset::IMU as i32 + IMU_BYTES;
+/// Every sensor, read at once.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SensorSnapshot {
+    /// When the values were read.
+    pub timestamp: Instant,
+    pub uv: f32,
+    pub pressure: Pressure,
+    pub humidity: Humidity,
+    pub imu: Imu,
+}
+
+impl SensorSnapshot {
+    /// Decode the sensors' raw values, starting at `mcu_offset::UV`.
+    pub(crate) fn from_raw(data: &[i32], timestamp: Instant) -> SensorSnapshot {
+        SensorSnapshot {
+            timestamp,
+            uv: from_fixed_point(data[0]),
+            pressure: Pressure::from_raw(sensor_data(data, mcu_offset::PRESSURE)),
+            humidity: Humidity::from_raw(sensor_data(data, mcu_offset::HUMIDITY)),
+            imu: Imu::from_raw(sensor_data(data, mcu_offset::IMU)),
+        }
+    }
+
+    /// Check that every value is within what the sensors can physically report.
+    pub fn validate(&self) -> Result<(), Error> {
+        check_range("UV index", self.uv, UV_RANGE)?;
+        self.pressure.validate()?;
+        self.humidity.validate()?;
+        self.imu.validate()
+    }
+
+    /// The values used to derive environmental metrics.
+    pub fn environment(&self) -> Environment {
+        Environment {
+            uv: self.uv,
+            pressure: self.pressure,
+            humidity: self.humidity,
+        }
+    }
+}
+
+/// The raw values of one sensor, out of the block starting at `mcu_offset::UV`.
+pub(crate) fn sensor_data(data: &[i32], offset: u16) -> &[i32] {
+    &data[(offset - mcu_offset::UV) as usize / 4..]
+}
+
+/// Fail if `value` is NaN or outside of `range`.
+pub(crate) fn check_range(name: &str, value: f32, range: RangeInclusive<f32>) -> Result<(), Error> {
+    if range.contains(&value) {
+        Ok(())
+    } else {
+        Err(Error::InvalidSensorReading(format!(
+            "{} of {} is outside of {:?}",
+            name, value, range
+        )))
+    }
 }
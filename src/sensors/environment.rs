@@ -0,0 +1,182 @@
+//! Environmental metrics derived from the UV, pressure and humidity sensors.
+//!
+//! ```
+//! use matrix_rhal::sensors::environment::{Environment, UvCategory};
+//! use matrix_rhal::sensors::units::{Celsius, Pascals, Percent};
+//! use matrix_rhal::sensors::{Humidity, Pressure};
+//!
+//! let environment = Environment {
+//!     uv: 6.5,
+//!     pressure: Pressure {
+//!         pressure: Pascals::SEA_LEVEL,
+//!         ..Default::default()
+//!     },
+//!     humidity: Humidity {
+//!         humidity: Percent(50.0),
+//!         temperature: Celsius(25.0),
+//!     },
+//! };
+//!
+//! assert!((environment.dew_point().0 - 13.9).abs() < 0.1);
+//! assert!((environment.absolute_humidity().grams_per_cubic_meter() - 11.5).abs() < 0.1);
+//! assert_eq!(environment.uv_category(), UvCategory::High);
+//! ```
+use super::units::{Celsius, KilogramsPerCubicMeter, Pascals};
+use super::{Humidity, Pressure};
+use std::time::Duration;
+
+/// Specific gas constant of dry air, in J/(kg·K).
+const DRY_AIR_GAS_CONSTANT: f32 = 287.058;
+/// Specific gas constant of water vapor, in J/(kg·K).
+const WATER_VAPOR_GAS_CONSTANT: f32 = 461.495;
+
+/// Erythemal dose that reddens unprotected fair skin (Fitzpatrick type II), in J/m².
+pub const MINIMAL_ERYTHEMAL_DOSE: f32 = 250.0;
+
+/// A reading of every environmental sensor.
+///
+/// The humidity sensor's temperature is used as the air temperature for every metric.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Environment {
+    /// UV index.
+    pub uv: f32,
+    pub pressure: Pressure,
+    pub humidity: Humidity,
+}
+
+impl Environment {
+    /// Partial pressure of water vapor in the air.
+    pub fn vapor_pressure(&self) -> Pascals {
+        Pascals(
+            saturation_vapor_pressure(self.humidity.temperature).0
+                * self.humidity.humidity.fraction(),
+        )
+    }
+
+    /// Temperature at which the air would be saturated, from the Magnus formula.
+    pub fn dew_point(&self) -> Celsius {
+        let (a, b) = (17.62, 243.12);
+        let temperature = self.humidity.temperature.0;
+        // a fully dry reading would give -infinity
+        let gamma =
+            self.humidity.humidity.fraction().max(0.001).ln() + a * temperature / (b + temperature);
+        Celsius(b * gamma / (a - gamma))
+    }
+
+    /// Apparent temperature in hot weather, from the US National Weather Service's regression.
+    pub fn heat_index(&self) -> Celsius {
+        let t = self.humidity.temperature.fahrenheit();
+        let rh = self.humidity.humidity.0;
+
+        let simple = 0.5 * (t + 61.0 + (t - 68.0) * 1.2 + rh * 0.094);
+        if (simple + t) / 2.0 < 80.0 {
+            return Celsius::from_fahrenheit(simple);
+        }
+
+        let mut index = -42.379 + 2.049_015_2 * t + 10.143_331 * rh
+            - 0.224_755_4 * t * rh
+            - 0.006_837_83 * t * t
+            - 0.054_817_17 * rh * rh
+            + 0.001_228_74 * t * t * rh
+            + 0.000_852_82 * t * rh * rh
+            - 0.000_001_99 * t * t * rh * rh;
+
+        if rh < 13.0 && (80.0..=112.0).contains(&t) {
+            index -= (13.0 - rh) / 4.0 * ((17.0 - (t - 95.0).abs()) / 17.0).sqrt();
+        } else if rh > 85.0 && (80.0..=87.0).contains(&t) {
+            index += (rh - 85.0) / 10.0 * (87.0 - t) / 5.0;
+        }
+
+        Celsius::from_fahrenheit(index)
+    }
+
+    /// Apparent temperature as defined by Environment Canada.
+    pub fn humidex(&self) -> Celsius {
+        let dew_point = self.dew_point().kelvin();
+        let vapor_pressure = 6.11 * (5417.753 * (1.0 / 273.16 - 1.0 / dew_point)).exp();
+        Celsius(self.humidity.temperature.0 + 0.5555 * (vapor_pressure - 10.0))
+    }
+
+    /// Mass of water vapor in a volume of air.
+    pub fn absolute_humidity(&self) -> KilogramsPerCubicMeter {
+        KilogramsPerCubicMeter(
+            self.vapor_pressure().0
+                / (WATER_VAPOR_GAS_CONSTANT * self.humidity.temperature.kelvin()),
+        )
+    }
+
+    /// Density of the humid air, which is lighter than dry air.
+    pub fn air_density(&self) -> KilogramsPerCubicMeter {
+        let vapor = self.vapor_pressure().0;
+        let dry = self.pressure.pressure.0 - vapor;
+        let temperature = self.humidity.temperature.kelvin();
+
+        KilogramsPerCubicMeter(
+            dry / (DRY_AIR_GAS_CONSTANT * temperature)
+                + vapor / (WATER_VAPOR_GAS_CONSTANT * temperature),
+        )
+    }
+
+    pub fn uv_category(&self) -> UvCategory {
+        UvCategory::from_index(self.uv)
+    }
+
+    /// How long unprotected fair skin can be exposed before reddening, or `None` if there is no UV at all, or so
+    /// little that the time doesn't fit in a `Duration`.
+    ///
+    /// Each point of UV index is 0.025 W/m² of erythemally weighted irradiance, which is accumulated up to
+    /// `MINIMAL_ERYTHEMAL_DOSE`. Darker skin tolerates about 1.5 to 4 times longer.
+    ///
+    /// # Example
+    /// ```
+    /// use matrix_rhal::sensors::environment::Environment;
+    /// use std::time::Duration;
+    ///
+    /// let sunny = Environment { uv: 8.0, ..Default::default() };
+    /// assert_eq!(sunny.max_exposure(), Some(Duration::from_secs(1250)));
+    ///
+    /// let broken = Environment { uv: f32::NAN, ..Default::default() };
+    /// assert_eq!(broken.max_exposure(), None);
+    /// assert_eq!(Environment { uv: 1e-38, ..broken }.max_exposure(), None);
+    /// ```
+    pub fn max_exposure(&self) -> Option<Duration> {
+        if !self.uv.is_finite() || self.uv <= 0.0 {
+            return None;
+        }
+
+        Duration::try_from_secs_f32(MINIMAL_ERYTHEMAL_DOSE / (self.uv * 0.025)).ok()
+    }
+}
+
+/// Saturation vapor pressure of water, from the Magnus formula.
+pub(crate) fn saturation_vapor_pressure(temperature: Celsius) -> Pascals {
+    Pascals(611.2 * (17.62 * temperature.0 / (243.12 + temperature.0)).exp())
+}
+
+/// Exposure categories of the UV index, as defined by the World Health Organization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UvCategory {
+    /// 0 to 2: no protection needed.
+    Low,
+    /// 3 to 5: seek shade around midday, wear a shirt, sunscreen and a hat.
+    Moderate,
+    /// 6 to 7: same as moderate.
+    High,
+    /// 8 to 10: avoid being outside around midday.
+    VeryHigh,
+    /// 11 and above: same as very high.
+    Extreme,
+}
+
+impl UvCategory {
+    /// Category of a UV index, which is rounded to the nearest integer first.
+    pub fn from_index(uv: f32) -> UvCategory {
+        match uv.round() as i32 {
+            i32::MIN..=2 => UvCategory::Low,
+            3..=5 => UvCategory::Moderate,
+            6..=7 => UvCategory::High,
+            8..=10 => UvCategory::VeryHigh,
+            _ => UvCategory::Extreme,
+        }
+    }
+}
@@ -0,0 +1,379 @@
+//! Filters to smooth noisy sensor values.
+//!
+//! Every filter takes one value at a time and returns the filtered value, so it can be attached to any channel of
+//! any sensor, such as the UV index or `Pressure::pressure`. Filters are chained with `then` and run over an
+//! iterator of values with `apply`:
+//!
+//! ```
+//! use matrix_rhal::sensors::filter::{Filter, MovingAverage, SpikeRejection};
+//!
+//! let readings = vec![3.0, 3.2, 9.0, 3.1, 2.9, 3.0];
+//!
+//! // drop the 9.0 glitch, then average the last 3 values
+//! let filter = SpikeRejection::new(1.0, 2).then(MovingAverage::new(3));
+//! let smoothed: Vec<f32> = filter.apply(readings).collect();
+//!
+//! assert!(smoothed.iter().all(|uv| (*uv - 3.0).abs() < 0.2));
+//! ```
+//!
+//! With a `SensorStream`, pick the channel out of each sample first:
+//!
+//! ```no_run
+//! use matrix_rhal::sensors::filter::{ExponentialSmoothing, Filter};
+//! use matrix_rhal::sensors::stream::{Reading, Sensor, SensorStream};
+//! use std::sync::Arc;
+//!
+//! let bus = Arc::new(matrix_rhal::Bus::init().unwrap());
+//! let stream = SensorStream::new(bus, &[(Sensor::Uv, 10.0)], 16);
+//!
+//! let uv = stream.filter_map(|sample| match sample.reading {
+//!     Ok(Reading::Uv(uv)) => Some(uv),
+//!     _ => None,
+//! });
+//!
+//! for uv in ExponentialSmoothing::new(0.1).apply(uv) {
+//!     println!("UV index: {:.1}", uv);
+//! }
+//! ```
+use std::collections::VecDeque;
+
+/// Turns a sequence of values into a smoother one.
+pub trait Filter {
+    /// Feed the next value and return the filtered value.
+    fn update(&mut self, value: f32) -> f32;
+
+    /// Forget every value seen so far.
+    fn reset(&mut self);
+
+    /// Feed the output of this filter into `next`.
+    fn then<F: Filter>(self, next: F) -> Chain<Self, F>
+    where
+        Self: Sized,
+    {
+        Chain {
+            first: self,
+            second: next,
+        }
+    }
+
+    /// Filter every value of an iterator.
+    fn apply<I: IntoIterator<Item = f32>>(self, values: I) -> Filtered<I::IntoIter, Self>
+    where
+        Self: Sized,
+    {
+        Filtered {
+            values: values.into_iter(),
+            filter: self,
+        }
+    }
+}
+
+impl<F: Filter + ?Sized> Filter for Box<F> {
+    fn update(&mut self, value: f32) -> f32 {
+        (**self).update(value)
+    }
+
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+}
+
+/// Two filters applied one after the other, see `Filter::then`.
+#[derive(Debug, Clone)]
+pub struct Chain<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A: Filter, B: Filter> Filter for Chain<A, B> {
+    fn update(&mut self, value: f32) -> f32 {
+        self.second.update(self.first.update(value))
+    }
+
+    fn reset(&mut self) {
+        self.first.reset();
+        self.second.reset();
+    }
+}
+
+/// An iterator of filtered values, see `Filter::apply`.
+#[derive(Debug, Clone)]
+pub struct Filtered<I, F> {
+    values: I,
+    filter: F,
+}
+
+impl<I: Iterator<Item = f32>, F: Filter> Iterator for Filtered<I, F> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let value = self.values.next()?;
+        Some(self.filter.update(value))
+    }
+}
+
+/// The last values fed to a filter.
+#[derive(Debug, Clone)]
+struct Window {
+    values: VecDeque<f32>,
+    size: usize,
+}
+
+impl Window {
+    fn new(size: usize) -> Window {
+        let size = size.max(1);
+        Window {
+            values: VecDeque::with_capacity(size),
+            size,
+        }
+    }
+
+    fn push(&mut self, value: f32) {
+        if self.values.len() == self.size {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+}
+
+/// Average of the last values.
+#[derive(Debug, Clone)]
+pub struct MovingAverage {
+    window: Window,
+}
+
+impl MovingAverage {
+    /// Return an instance of MovingAverage over the last `size` values, at least 1.
+    pub fn new(size: usize) -> MovingAverage {
+        MovingAverage {
+            window: Window::new(size),
+        }
+    }
+}
+
+impl Filter for MovingAverage {
+    fn update(&mut self, value: f32) -> f32 {
+        self.window.push(value);
+        self.window.values.iter().sum::<f32>() / self.window.values.len() as f32
+    }
+
+    fn reset(&mut self) {
+        self.window.values.clear();
+    }
+}
+
+/// Exponentially weighted moving average: `output = alpha * value + (1 - alpha) * previous output`.
+#[derive(Debug, Clone)]
+pub struct ExponentialSmoothing {
+    alpha: f32,
+    value: Option<f32>,
+}
+
+impl ExponentialSmoothing {
+    /// Return an instance of ExponentialSmoothing. `alpha` goes from `0.0`, which never changes, to `1.0`, which
+    /// doesn't smooth at all. A NaN or infinite `alpha` doesn't smooth either.
+    ///
+    /// # Example
+    /// ```
+    /// use matrix_rhal::sensors::filter::{ExponentialSmoothing, Filter};
+    ///
+    /// let values: Vec<f32> = ExponentialSmoothing::new(f32::NAN).apply(vec![1.0, 2.0]).collect();
+    /// assert_eq!(values, [1.0, 2.0]);
+    /// ```
+    pub fn new(alpha: f32) -> ExponentialSmoothing {
+        let alpha = if alpha.is_finite() {
+            alpha.clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+
+        ExponentialSmoothing { alpha, value: None }
+    }
+}
+
+impl Filter for ExponentialSmoothing {
+    fn update(&mut self, value: f32) -> f32 {
+        let smoothed = match self.value {
+            Some(previous) => self.alpha * value + (1.0 - self.alpha) * previous,
+            None => value,
+        };
+
+        self.value = Some(smoothed);
+        smoothed
+    }
+
+    fn reset(&mut self) {
+        self.value = None;
+    }
+}
+
+/// Median of the last values, which ignores isolated outliers entirely.
+#[derive(Debug, Clone)]
+pub struct Median {
+    window: Window,
+}
+
+impl Median {
+    /// Return an instance of Median over the last `size` values, at least 1. Odd sizes avoid averaging the two
+    /// middle values.
+    pub fn new(size: usize) -> Median {
+        Median {
+            window: Window::new(size),
+        }
+    }
+}
+
+impl Filter for Median {
+    fn update(&mut self, value: f32) -> f32 {
+        self.window.push(value);
+
+        let mut sorted: Vec<f32> = self.window.values.iter().copied().collect();
+        sorted.sort_by(f32::total_cmp);
+
+        let middle = sorted.len() / 2;
+        if sorted.len() % 2 == 1 {
+            sorted[middle]
+        } else {
+            (sorted[middle - 1] + sorted[middle]) / 2.0
+        }
+    }
+
+    fn reset(&mut self) {
+        self.window.values.clear();
+    }
+}
+
+/// Minimum, maximum, mean and variance of the last values. As a filter, it returns the mean.
+#[derive(Debug, Clone)]
+pub struct WindowStats {
+    window: Window,
+}
+
+impl WindowStats {
+    /// Return an instance of WindowStats over the last `size` values, at least 1.
+    pub fn new(size: usize) -> WindowStats {
+        WindowStats {
+            window: Window::new(size),
+        }
+    }
+
+    /// Number of values in the window, up to its size.
+    pub fn len(&self) -> usize {
+        self.window.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.window.values.is_empty()
+    }
+
+    pub fn min(&self) -> Option<f32> {
+        self.window.values.iter().copied().reduce(f32::min)
+    }
+
+    pub fn max(&self) -> Option<f32> {
+        self.window.values.iter().copied().reduce(f32::max)
+    }
+
+    pub fn mean(&self) -> Option<f32> {
+        if self.is_empty() {
+            return None;
+        }
+
+        Some(self.window.values.iter().sum::<f32>() / self.len() as f32)
+    }
+
+    /// Population variance.
+    pub fn variance(&self) -> Option<f32> {
+        let mean = self.mean()?;
+        Some(
+            self.window
+                .values
+                .iter()
+                .map(|value| (value - mean).powi(2))
+                .sum::<f32>()
+                / self.len() as f32,
+        )
+    }
+
+    pub fn standard_deviation(&self) -> Option<f32> {
+        self.variance().map(f32::sqrt)
+    }
+}
+
+impl Filter for WindowStats {
+    fn update(&mut self, value: f32) -> f32 {
+        self.window.push(value);
+        self.mean().unwrap_or(value)
+    }
+
+    fn reset(&mut self) {
+        self.window.values.clear();
+    }
+}
+
+/// Replaces sudden jumps with the last accepted value.
+///
+/// A value that differs from the last accepted one by more than `max_change` is rejected, unless the previous
+/// `max_rejections` values were all rejected too: the jump is then considered a real change and accepted.
+///
+/// NaN and infinite values are always rejected, without counting towards `max_rejections`. Before any value has
+/// been accepted there is nothing to replace them with, so they are passed through.
+///
+/// # Example
+/// ```
+/// use matrix_rhal::sensors::filter::{Filter, SpikeRejection};
+///
+/// let filter = SpikeRejection::new(1.0, 2);
+/// let values: Vec<f32> = filter.apply(vec![3.0, f32::NAN, 9.0, 3.1, 3.0]).collect();
+/// assert_eq!(values, [3.0, 3.0, 3.0, 3.1, 3.0]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SpikeRejection {
+    max_change: f32,
+    max_rejections: usize,
+    last: Option<f32>,
+    rejections: usize,
+}
+
+impl SpikeRejection {
+    /// Return an instance of SpikeRejection.
+    pub fn new(max_change: f32, max_rejections: usize) -> SpikeRejection {
+        SpikeRejection {
+            max_change,
+            max_rejections,
+            last: None,
+            rejections: 0,
+        }
+    }
+
+    /// Number of values rejected in a row so far.
+    pub fn rejections(&self) -> usize {
+        self.rejections
+    }
+}
+
+impl Filter for SpikeRejection {
+    fn update(&mut self, value: f32) -> f32 {
+        match self.last {
+            Some(last) if !value.is_finite() => last,
+            None if !value.is_finite() => value,
+            Some(last)
+                if (value - last).abs() > self.max_change
+                    && self.rejections < self.max_rejections =>
+            {
+                self.rejections += 1;
+                last
+            }
+            _ => {
+                self.last = Some(value);
+                self.rejections = 0;
+                value
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.last = None;
+        self.rejections = 0;
+    }
+}
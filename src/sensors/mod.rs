@@ -1,23 +1,370 @@
 use crate::bus::memory_map::*;
-use crate::{Bus, Device};
-mod data;
-use data::*;
+use crate::{Bus, Device, Error};
+use calibration::*;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use units::from_fixed_point;
+pub mod ahrs;
+pub mod altitude;
+pub mod calibration;
+pub mod data;
+pub mod environment;
+pub mod filter;
+pub mod stream;
+pub mod units;
+pub use data::*;
+
+/// How long a sensor's raw values can stay exactly the same before `try_read_*` reports them as stale.
+pub const DEFAULT_STALE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Everything that changes how `Sensors` reads values, to set up other instances the same way, such as the ones
+/// polling a `SensorStream`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SensorSettings {
+    /// See `Sensors::set_stale_timeout`.
+    pub stale_timeout: Option<Duration>,
+    /// Corrections applied to every IMU reading.
+    pub calibration: ImuCalibration,
+    /// Corrections applied to every humidity and pressure reading.
+    pub temperature_compensation: SelfHeatingCompensation,
+}
+
+impl Default for SensorSettings {
+    /// The default stale timeout, without any calibration.
+    fn default() -> Self {
+        SensorSettings {
+            stale_timeout: Some(DEFAULT_STALE_TIMEOUT),
+            calibration: ImuCalibration::default(),
+            temperature_compensation: SelfHeatingCompensation::default(),
+        }
+    }
+}
+
+impl SensorSettings {
+    /// The default stale timeout, with the calibration profile and self-heating model saved for a board.
+    ///
+    /// Fails if either exists but cannot be read or parsed.
+    pub fn load(bus: &Bus) -> Result<SensorSettings, Error> {
+        Ok(SensorSettings {
+            calibration: ImuCalibration::load_profile(bus)?,
+            temperature_compensation: SelfHeatingCompensation::load_profile(bus)?,
+            ..SensorSettings::default()
+        })
+    }
+
+    /// Same as `load`, but a profile that cannot be read or parsed is replaced with no calibration at all.
+    pub fn load_or_default(bus: &Bus) -> SensorSettings {
+        SensorSettings {
+            calibration: ImuCalibration::load_profile(bus).unwrap_or_default(),
+            temperature_compensation: SelfHeatingCompensation::load_profile(bus)
+                .unwrap_or_default(),
+            ..SensorSettings::default()
+        }
+    }
+}
 
 /// Communicates with the main sensors on the MATRIX Creator.
 #[derive(Debug)]
 pub struct Sensors<'a> {
     pub bus: &'a Bus,
+    stale_timeout: Mutex<Option<Duration>>,
+    /// Last raw values of each sensor, keyed by MCU offset, and when they last changed.
+    last_change: Mutex<HashMap<u16, (Vec<i32>, Instant)>>,
+    /// Corrections applied to every IMU reading.
+    calibration: Mutex<ImuCalibration>,
+    /// Corrections applied to every humidity and pressure reading.
+    temperature_compensation: Mutex<SelfHeatingCompensation>,
 }
 
 // Read function for each sensor.
 impl<'a> Sensors<'a> {
     /// Creates a new instance of Sensors.
+    ///
+    /// If a calibration profile was saved for this board, it is applied to every IMU reading. The same goes for its
+    /// self-heating model and the humidity and pressure readings. A profile that cannot be read or parsed is
+    /// ignored, use `try_new` to report it instead.
+    ///
+    /// # Panics
+    /// If the device is not a MATRIX Creator.
     pub fn new(bus: &Bus) -> Sensors {
+        Sensors::with_settings(bus, SensorSettings::load_or_default(bus))
+    }
+
+    /// Fallible version of `new`. Returns an error if the device is not a MATRIX Creator or its calibration profile
+    /// exists but cannot be read or parsed.
+    pub fn try_new(bus: &Bus) -> Result<Sensors<'_>, Error> {
+        if bus.device_name != Device::Creator {
+            return Err(Error::UnsupportedDevice);
+        }
+
+        Ok(Sensors::with_settings(bus, SensorSettings::load(bus)?))
+    }
+
+    /// Creates a new instance of Sensors with the given settings instead of the ones saved for the board.
+    ///
+    /// # Panics
+    /// If the device is not a MATRIX Creator.
+    pub fn with_settings(bus: &Bus, settings: SensorSettings) -> Sensors<'_> {
         if bus.device_name != Device::Creator {
             panic!("Sensors are only available on the MATRIX Creator!")
         }
 
-        Sensors { bus }
+        Sensors {
+            bus,
+            stale_timeout: Mutex::new(settings.stale_timeout),
+            last_change: Mutex::new(HashMap::new()),
+            calibration: Mutex::new(settings.calibration),
+            temperature_compensation: Mutex::new(settings.temperature_compensation),
+        }
+    }
+
+    /// Return the current settings, to set up another instance the same way.
+    pub fn settings(&self) -> Result<SensorSettings, Error> {
+        Ok(SensorSettings {
+            stale_timeout: *self.stale_timeout.lock()?,
+            calibration: *self.calibration.lock()?,
+            temperature_compensation: *self.temperature_compensation.lock()?,
+        })
+    }
+
+    /// Set the corrections applied to every IMU reading.
+    pub fn set_calibration(&self, calibration: ImuCalibration) -> Result<(), Error> {
+        *self.calibration.lock()? = calibration;
+        Ok(())
+    }
+
+    /// Return the corrections currently applied to IMU readings.
+    pub fn calibration(&self) -> Result<ImuCalibration, Error> {
+        Ok(*self.calibration.lock()?)
+    }
+
+    /// Set the correction applied to every magnetometer reading. `None` returns raw readings.
+    pub fn set_mag_calibration(&self, calibration: Option<MagCalibration>) -> Result<(), Error> {
+        self.calibration.lock()?.mag = calibration;
+        Ok(())
+    }
+
+    /// Return the correction currently applied to magnetometer readings.
+    pub fn mag_calibration(&self) -> Result<Option<MagCalibration>, Error> {
+        Ok(self.calibration.lock()?.mag)
+    }
+
+    /// Save the current calibration as this board's profile, so that it is used from now on.
+    pub fn save_calibration(&self) -> Result<(), Error> {
+        self.calibration()?
+            .save(ImuCalibration::profile_path(self.bus))
+    }
+
+    /// Set the self-heating model applied to every humidity and pressure reading.
+    ///
+    /// If it has a CPU factor, the CPU temperature is read along with each reading. When that fails, only the
+    /// offsets are applied.
+    pub fn set_temperature_compensation(
+        &self,
+        compensation: SelfHeatingCompensation,
+    ) -> Result<(), Error> {
+        *self.temperature_compensation.lock()? = compensation;
+        Ok(())
+    }
+
+    /// Return the self-heating model currently applied to humidity and pressure readings.
+    pub fn temperature_compensation(&self) -> Result<SelfHeatingCompensation, Error> {
+        Ok(*self.temperature_compensation.lock()?)
+    }
+
+    /// Save the current self-heating model as this board's, so that it is used from now on.
+    pub fn save_temperature_compensation(&self) -> Result<(), Error> {
+        self.temperature_compensation()?
+            .save(SelfHeatingCompensation::profile_path(self.bus))
+    }
+
+    /// Collect magnetometer readings for `duration` while the board is rotated in every direction, then fit and
+    /// start using a new magnetometer calibration.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// # let bus = matrix_rhal::Bus::init().unwrap();
+    /// let sensors = matrix_rhal::Sensors::new(&bus);
+    ///
+    /// println!("Slowly rotate the board in every direction...");
+    /// sensors.calibrate_magnetometer(Duration::from_secs(30)).unwrap();
+    /// sensors.save_calibration().unwrap();
+    /// ```
+    pub fn calibrate_magnetometer(&self, duration: Duration) -> Result<MagCalibration, Error> {
+        let mut calibrator = MagCalibrator::new();
+        for imu in self.collect_raw_imu(duration)? {
+            calibrator.add(&imu);
+        }
+
+        let mag = calibrator.fit()?;
+        self.calibration.lock()?.mag = Some(mag);
+        Ok(mag)
+    }
+
+    /// Estimate the gyroscope bias from readings collected for `duration` and start removing it. The board must
+    /// stay perfectly still.
+    pub fn calibrate_gyro(&self, duration: Duration) -> Result<[f32; 3], Error> {
+        let bias = estimate_gyro_bias(&self.collect_raw_imu(duration)?)?;
+        self.calibration.lock()?.gyro_bias = Some(bias);
+        Ok(bias)
+    }
+
+    /// Record one of the six resting positions of an accelerometer calibration, from readings collected for
+    /// `duration`. The board must stay perfectly still.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use matrix_rhal::sensors::calibration::AccelCalibrator;
+    /// use std::time::Duration;
+    ///
+    /// # let bus = matrix_rhal::Bus::init().unwrap();
+    /// let sensors = matrix_rhal::Sensors::new(&bus);
+    /// let mut calibrator = AccelCalibrator::new();
+    ///
+    /// while let Some(next) = calibrator.missing().first() {
+    ///     println!("Rest the board with {:?}, then press enter", next);
+    ///     std::io::stdin().read_line(&mut String::new()).unwrap();
+    ///
+    ///     let position = sensors
+    ///         .calibrate_accel_position(&mut calibrator, Duration::from_secs(2))
+    ///         .unwrap();
+    ///     println!("Recorded {:?}", position);
+    /// }
+    ///
+    /// let mut calibration = sensors.calibration().unwrap();
+    /// calibration.accel = Some(calibrator.fit().unwrap());
+    /// sensors.set_calibration(calibration).unwrap();
+    /// sensors.save_calibration().unwrap();
+    /// ```
+    pub fn calibrate_accel_position(
+        &self,
+        calibrator: &mut AccelCalibrator,
+        duration: Duration,
+    ) -> Result<Position, Error> {
+        calibrator.add_readings(&self.collect_raw_imu(duration)?)
+    }
+
+    /// Set how long a sensor's values can stay exactly the same before `try_read_*` returns
+    /// `Error::StaleSensorData`. `None` disables the check.
+    ///
+    /// Live sensors always have some noise, so values frozen for a long time usually mean the MCU stopped updating.
+    /// The UV sensor is never checked since it legitimately reads 0 in the dark.
+    pub fn set_stale_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        *self.stale_timeout.lock()? = timeout;
+        Ok(())
+    }
+
+    /// Fallible version of `read_uv`. Returns an error if the bus fails or the value is implausible.
+    pub fn try_read_uv(&self) -> Result<f32, Error> {
+        let data = self.try_read_raw(mcu_offset::UV, UV_BYTES)?;
+        let uv = from_fixed_point(data[0]);
+
+        check_range("UV index", uv, UV_RANGE)?;
+        Ok(uv)
+    }
+
+    /// Fallible version of `read_pressure`. Returns an error if the bus fails, the values are implausible or
+    /// they have stopped changing.
+    pub fn try_read_pressure(&self) -> Result<Pressure, Error> {
+        let data = self.try_read_raw(mcu_offset::PRESSURE, PRESSURE_BYTES)?;
+        self.check_fresh("pressure", mcu_offset::PRESSURE, &data)?;
+
+        let mut pressure = Pressure::from_raw(&data);
+        pressure.validate()?;
+        self.compensate(|compensation, cpu| {
+            compensation.pressure.apply_pressure(&mut pressure, cpu)
+        })?;
+        Ok(pressure)
+    }
+
+    /// Fallible version of `read_humidity`. Returns an error if the bus fails, the values are implausible or
+    /// they have stopped changing.
+    pub fn try_read_humidity(&self) -> Result<Humidity, Error> {
+        let data = self.try_read_raw(mcu_offset::HUMIDITY, HUMIDITY_BYTES)?;
+        self.check_fresh("humidity", mcu_offset::HUMIDITY, &data)?;
+
+        let mut humidity = Humidity::from_raw(&data);
+        humidity.validate()?;
+        self.compensate(|compensation, cpu| {
+            compensation.humidity.apply_humidity(&mut humidity, cpu)
+        })?;
+        Ok(humidity)
+    }
+
+    /// Fallible version of `read_imu`. Returns an error if the bus fails, the values are implausible (including
+    /// NaN angles) or they have stopped changing.
+    pub fn try_read_imu(&self) -> Result<Imu, Error> {
+        let data = self.try_read_raw(mcu_offset::IMU, IMU_BYTES)?;
+        self.check_fresh("IMU", mcu_offset::IMU, &data)?;
+
+        let mut imu = Imu::from_raw(&data);
+        imu.validate()?;
+        self.calibrate(&mut imu)?;
+        Ok(imu)
+    }
+
+    /// Read the UV, pressure and humidity sensors together, to compute derived metrics such as the dew point.
+    pub fn try_read_environment(&self) -> Result<environment::Environment, Error> {
+        Ok(environment::Environment {
+            uv: self.try_read_uv()?,
+            pressure: self.try_read_pressure()?,
+            humidity: self.try_read_humidity()?,
+        })
+    }
+
+    /// Fallible version of `read_all`. Returns an error if the bus fails, any value is implausible or a sensor's
+    /// values have stopped changing.
+    pub fn try_read_all(&self) -> Result<SensorSnapshot, Error> {
+        let data = self.try_read_raw(mcu_offset::UV, ALL_SENSORS_BYTES)?;
+        let timestamp = Instant::now();
+
+        for (sensor, offset) in &[
+            ("pressure", mcu_offset::PRESSURE),
+            ("humidity", mcu_offset::HUMIDITY),
+            ("IMU", mcu_offset::IMU),
+        ] {
+            self.check_fresh(sensor, *offset, sensor_data(&data, *offset))?;
+        }
+
+        let mut snapshot = SensorSnapshot::from_raw(&data, timestamp);
+        snapshot.validate()?;
+        self.calibrate_snapshot(&mut snapshot)?;
+        Ok(snapshot)
+    }
+
+    /// Return the latest values of every sensor, fetched with a single bus transfer.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # let bus = matrix_rhal::Bus::init().unwrap();
+    /// let sensors = matrix_rhal::Sensors::new(&bus);
+    ///
+    /// let snapshot = sensors.read_all();
+    /// println!(
+    ///     "{:?}: {}, {}",
+    ///     snapshot.timestamp, snapshot.humidity.temperature, snapshot.imu.accel_z
+    /// );
+    /// ```
+    pub fn read_all(&self) -> SensorSnapshot {
+        const BUFFER_LENGTH: usize = get_buffer_length(ALL_SENSORS_BYTES);
+
+        // create read buffer
+        let mut data: [i32; BUFFER_LENGTH] = [0; BUFFER_LENGTH];
+        data[0] = (fpga_address::MCU + (mcu_offset::UV >> 1)) as i32;
+        data[1] = ALL_SENSORS_BYTES;
+
+        // populate read buffer
+        self.bus
+            .read(unsafe { std::mem::transmute::<&mut [i32], &mut [u8]>(&mut data) });
+
+        let mut snapshot = SensorSnapshot::from_raw(&data[2..], Instant::now());
+        self.calibrate_snapshot(&mut snapshot)
+            .expect("sensor calibration lock poisoned");
+        snapshot
     }
 
     /// Return the latest UV sensor value.
@@ -33,7 +380,7 @@ impl<'a> Sensors<'a> {
         self.bus
             .read(unsafe { std::mem::transmute::<&mut [i32], &mut [u8]>(&mut data) });
 
-        data[2] as f32 / 1000.0
+        from_fixed_point(data[2])
     }
 
     /// Return the latest Pressure sensor values.
@@ -49,11 +396,12 @@ impl<'a> Sensors<'a> {
         self.bus
             .read(unsafe { std::mem::transmute::<&mut [i32], &mut [u8]>(&mut data) });
 
-        Pressure {
-            pressure: data[3] as f32 / 1000.0,
-            altitude: data[2] as f32 / 1000.0,
-            temperature: data[4] as f32 / 1000.0,
-        }
+        let mut pressure = Pressure::from_raw(&data[2..]);
+        self.compensate(|compensation, cpu| {
+            compensation.pressure.apply_pressure(&mut pressure, cpu)
+        })
+        .expect("temperature compensation lock poisoned");
+        pressure
     }
 
     /// Return the latest Humidity sensor values.
@@ -69,10 +417,12 @@ impl<'a> Sensors<'a> {
         self.bus
             .read(unsafe { std::mem::transmute::<&mut [i32], &mut [u8]>(&mut data) });
 
-        Humidity {
-            humidity: data[2] as f32 / 1000.0,
-            temperature: data[3] as f32 / 1000.0,
-        }
+        let mut humidity = Humidity::from_raw(&data[2..]);
+        self.compensate(|compensation, cpu| {
+            compensation.humidity.apply_humidity(&mut humidity, cpu)
+        })
+        .expect("temperature compensation lock poisoned");
+        humidity
     }
 
     /// Return the latest IMU sensor values.
@@ -88,28 +438,101 @@ impl<'a> Sensors<'a> {
         self.bus
             .read(unsafe { std::mem::transmute::<&mut [i32], &mut [u8]>(&mut data) });
 
-        Imu {
-            accel_x: data[2] as f32 / 1000.0,
-            accel_y: data[3] as f32 / 1000.0,
-            accel_z: data[4] as f32 / 1000.0,
+        let mut imu = Imu::from_raw(&data[2..]);
+        self.calibrate(&mut imu)
+            .expect("IMU calibration lock poisoned");
+        imu
+    }
+}
 
-            gyro_x: data[5] as f32 / 1000.0,
-            gyro_y: data[6] as f32 / 1000.0,
-            gyro_z: data[7] as f32 / 1000.0,
+// Helpers for the fallible reads.
+impl<'a> Sensors<'a> {
+    /// Apply the calibration profile.
+    fn calibrate(&self, imu: &mut Imu) -> Result<(), Error> {
+        self.calibration.lock()?.apply(imu);
+        Ok(())
+    }
+
+    /// Apply the IMU calibration and the self-heating model to a snapshot.
+    fn calibrate_snapshot(&self, snapshot: &mut SensorSnapshot) -> Result<(), Error> {
+        self.calibrate(&mut snapshot.imu)?;
+        self.compensate(|compensation, cpu| {
+            compensation
+                .pressure
+                .apply_pressure(&mut snapshot.pressure, cpu);
+            compensation
+                .humidity
+                .apply_humidity(&mut snapshot.humidity, cpu);
+        })
+    }
+
+    /// Run `apply` with the self-heating model and, if it needs one, the CPU temperature.
+    fn compensate<F>(&self, apply: F) -> Result<(), Error>
+    where
+        F: FnOnce(&SelfHeatingCompensation, Option<units::Celsius>),
+    {
+        let compensation = *self.temperature_compensation.lock()?;
+        let cpu = if compensation.uses_cpu_temperature() {
+            read_cpu_temperature().ok()
+        } else {
+            None
+        };
+
+        apply(&compensation, cpu);
+        Ok(())
+    }
 
-            mag_x: data[8] as f32 / 1000.0,
-            mag_y: data[9] as f32 / 1000.0,
-            mag_z: data[10] as f32 / 1000.0,
+    /// Collect uncalibrated IMU readings for `duration`, at about 50Hz.
+    fn collect_raw_imu(&self, duration: Duration) -> Result<Vec<Imu>, Error> {
+        let mut readings = Vec::new();
+        let started = Instant::now();
 
-            // TODO: ask why we have these. They seem to be unused.
-            mag_offset_x: data[11] as f32,
-            mag_offset_y: data[12] as f32,
-            mag_offset_z: data[13] as f32,
+        while started.elapsed() < duration {
+            let data = self.try_read_raw(mcu_offset::IMU, IMU_BYTES)?;
+            readings.push(Imu::from_raw(&data));
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        Ok(readings)
+    }
+
+    /// Read a sensor's raw values, without the `address` and `byte_length` of the read buffer.
+    fn try_read_raw(&self, offset: u16, bytes: i32) -> Result<Vec<i32>, Error> {
+        let mut data = vec![0i32; get_buffer_length(bytes)];
+        data[0] = (fpga_address::MCU + (offset >> 1)) as i32;
+        data[1] = bytes;
+
+        self.bus
+            .try_read(unsafe { std::mem::transmute::<&mut [i32], &mut [u8]>(&mut data) })?;
+
+        data.drain(..2);
+        Ok(data)
+    }
+
+    /// Remember when a sensor's raw values last changed and fail if that was too long ago.
+    fn check_fresh(&self, sensor: &str, offset: u16, data: &[i32]) -> Result<(), Error> {
+        let timeout = *self.stale_timeout.lock()?;
+        let now = Instant::now();
+
+        let last_change = &mut *self.last_change.lock()?;
+        let (previous, changed) = last_change
+            .entry(offset)
+            .or_insert_with(|| (data.to_vec(), now));
+
+        if previous.as_slice() != data {
+            *previous = data.to_vec();
+            *changed = now;
+        }
 
-            // These values are already floats so we just need to treat them as one.
-            yaw: f32::from_bits(data[14] as u32),
-            pitch: f32::from_bits(data[15] as u32),
-            roll: f32::from_bits(data[16] as u32),
+        match timeout {
+            Some(timeout) if now.duration_since(*changed) > timeout => {
+                Err(Error::StaleSensorData(format!(
+                    "{} values have not changed for {:?}",
+                    sensor,
+                    now.duration_since(*changed)
+                )))
+            }
+            _ => Ok(()),
         }
     }
 }
@@ -0,0 +1,310 @@
+//! Continuous sensor polling on a background thread.
+//!
+//! Each sensor is read at its own rate and every reading is sent to the consumer with a monotonic timestamp and a
+//! sequence number. Readings are queued in a bounded channel; when the consumer falls behind, new readings are
+//! dropped and counted as overruns instead of piling up.
+use super::{Humidity, Imu, Pressure, SensorSettings, Sensors};
+use crate::{Bus, Error};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Fastest rate a sensor is polled at, in Hz. Faster rates are lowered to it.
+pub const MAX_RATE: f32 = 1000.0;
+/// Slowest rate a sensor is polled at, in Hz. Slower rates are raised to it.
+pub const MIN_RATE: f32 = 0.01;
+
+/// A sensor that can be polled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sensor {
+    Uv,
+    Pressure,
+    Humidity,
+    Imu,
+}
+
+/// Values read from a single sensor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reading {
+    Uv(f32),
+    Pressure(Pressure),
+    Humidity(Humidity),
+    Imu(Imu),
+}
+
+/// A reading delivered by a `SensorStream`.
+#[derive(Debug)]
+pub struct Sample {
+    /// Sensor that was read.
+    pub sensor: Sensor,
+    /// Position of this sample in the stream, starting at 0. Dropped samples leave gaps.
+    pub sequence: u64,
+    /// When the sensor was read.
+    pub timestamp: Instant,
+    /// Total number of samples dropped so far because the consumer fell behind.
+    pub overruns: u64,
+    /// The reading, or why it failed. Failed reads do not stop the stream.
+    pub reading: Result<Reading, Error>,
+}
+
+/// Polls sensors at independent rates on a background thread.
+///
+/// Polling stops when the stream is dropped.
+///
+/// # Example
+/// ```no_run
+/// use matrix_rhal::sensors::stream::{Reading, Sensor, SensorStream};
+/// use std::sync::Arc;
+///
+/// let bus = Arc::new(matrix_rhal::Bus::init().unwrap());
+///
+/// // IMU at 50Hz and humidity once per second
+/// let stream = SensorStream::new(bus, &[(Sensor::Imu, 50.0), (Sensor::Humidity, 1.0)], 64);
+///
+/// for sample in stream {
+///     if let Ok(Reading::Humidity(humidity)) = sample.reading {
+///         println!("{:?}: {}%", sample.timestamp, humidity.humidity);
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct SensorStream {
+    receiver: Receiver<Sample>,
+    poller: Poller,
+}
+
+impl SensorStream {
+    /// Start polling each `(sensor, rate)` pair, with rates in Hz between `MIN_RATE` and `MAX_RATE`. At most
+    /// `capacity` samples are queued for the consumer before new ones are dropped.
+    ///
+    /// Readings use the settings saved for the board, the same way `Sensors::new` does.
+    ///
+    /// # Panics
+    /// If the device has no sensors or a rate is not a positive number.
+    ///
+    /// ```should_panic
+    /// # let bus = matrix_rhal::Bus { device_file: "/dev/null", regmap_fd: -1, device_name: matrix_rhal::Device::Creator, device_version: 0, device_leds: 35, fpga_frequency: 0 };
+    /// use matrix_rhal::sensors::stream::{Sensor, SensorStream};
+    /// use std::sync::Arc;
+    ///
+    /// SensorStream::new(Arc::new(bus), &[(Sensor::Imu, f32::NAN)], 16);
+    /// ```
+    pub fn new(bus: Arc<Bus>, subscriptions: &[(Sensor, f32)], capacity: usize) -> SensorStream {
+        let settings = SensorSettings::load_or_default(&bus);
+        SensorStream::with_settings(bus, subscriptions, capacity, settings)
+    }
+
+    /// Same as `new`, but readings use the given settings, such as the ones of an existing `Sensors` instance.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use matrix_rhal::sensors::stream::{Sensor, SensorStream};
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// let bus = Arc::new(matrix_rhal::Bus::init().unwrap());
+    /// let sensors = matrix_rhal::Sensors::new(&bus);
+    /// sensors.calibrate_gyro(Duration::from_secs(5)).unwrap();
+    ///
+    /// let settings = sensors.settings().unwrap();
+    /// let stream = SensorStream::with_settings(Arc::clone(&bus), &[(Sensor::Imu, 50.0)], 64, settings);
+    /// ```
+    pub fn with_settings(
+        bus: Arc<Bus>,
+        subscriptions: &[(Sensor, f32)],
+        capacity: usize,
+        settings: SensorSettings,
+    ) -> SensorStream {
+        let (sender, receiver) = mpsc::sync_channel(capacity.max(1));
+        let poller = Poller::spawn(bus, subscriptions, settings, move |sample| {
+            match sender.try_send(sample) {
+                Ok(()) => Delivery::Sent,
+                Err(TrySendError::Full(_)) => Delivery::Full,
+                Err(TrySendError::Disconnected(_)) => Delivery::Closed,
+            }
+        });
+
+        SensorStream { receiver, poller }
+    }
+
+    /// Wait for the next sample. Returns `None` once polling has stopped.
+    pub fn recv(&self) -> Option<Sample> {
+        self.receiver.recv().ok()
+    }
+
+    /// Wait up to `timeout` for the next sample.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Sample> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+
+    /// Return the next sample if one is already waiting.
+    pub fn try_recv(&self) -> Option<Sample> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Total number of samples dropped so far because the consumer fell behind.
+    pub fn overruns(&self) -> u64 {
+        self.poller.overruns()
+    }
+
+    /// Stop polling. Samples already queued can still be received.
+    pub fn stop(&mut self) {
+        self.poller.stop();
+    }
+}
+
+impl Iterator for SensorStream {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        self.recv()
+    }
+}
+
+/// What happened to a sample handed to the consumer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Delivery {
+    Sent,
+    /// The consumer is behind. The sample was dropped.
+    Full,
+    /// The consumer is gone. Polling stops.
+    Closed,
+}
+
+/// Background thread polling the sensors, shared by the blocking and async streams.
+#[derive(Debug)]
+pub(crate) struct Poller {
+    running: Arc<AtomicBool>,
+    overruns: Arc<AtomicU64>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Poller {
+    /// Start polling each `(sensor, rate)` pair with the given settings, handing every sample to `deliver`.
+    ///
+    /// Panics if the device has no sensors, the same way `Sensors::with_settings` does, or if a rate is not a
+    /// positive number.
+    pub(crate) fn spawn<F>(
+        bus: Arc<Bus>,
+        subscriptions: &[(Sensor, f32)],
+        settings: SensorSettings,
+        deliver: F,
+    ) -> Poller
+    where
+        F: FnMut(Sample) -> Delivery + Send + 'static,
+    {
+        // fail on the caller's thread rather than in the background
+        Sensors::with_settings(&bus, settings);
+
+        let running = Arc::new(AtomicBool::new(true));
+        let overruns = Arc::new(AtomicU64::new(0));
+
+        let subscriptions: Vec<(Sensor, Duration)> = subscriptions
+            .iter()
+            .map(|(sensor, rate)| {
+                if !rate.is_finite() || *rate <= 0.0 {
+                    panic!("Invalid polling rate for {:?}: {}Hz", sensor, rate)
+                }
+
+                let rate = rate.clamp(MIN_RATE, MAX_RATE);
+                (*sensor, Duration::from_secs_f32(1.0 / rate))
+            })
+            .collect();
+
+        let thread = {
+            let running = Arc::clone(&running);
+            let overruns = Arc::clone(&overruns);
+            thread::spawn(move || {
+                let sensors = Sensors::with_settings(&bus, settings);
+                poll(&sensors, &subscriptions, deliver, &running, &overruns)
+            })
+        };
+
+        Poller {
+            running,
+            overruns,
+            thread: Some(thread),
+        }
+    }
+
+    pub(crate) fn overruns(&self) -> u64 {
+        self.overruns.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn stop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Body of the polling thread.
+fn poll<F>(
+    sensors: &Sensors,
+    subscriptions: &[(Sensor, Duration)],
+    mut deliver: F,
+    running: &AtomicBool,
+    overruns: &AtomicU64,
+) where
+    F: FnMut(Sample) -> Delivery,
+{
+    let started = Instant::now();
+    let mut due: Vec<Instant> = vec![started; subscriptions.len()];
+    let mut sequence = 0;
+
+    while running.load(Ordering::Relaxed) {
+        let (index, next) = match due.iter().enumerate().min_by_key(|(_, due)| **due) {
+            Some((index, next)) => (index, *next),
+            None => return,
+        };
+
+        // sleep in short steps so that `stop` is not held up by slow rates
+        let now = Instant::now();
+        if next > now {
+            thread::sleep((next - now).min(Duration::from_millis(50)));
+            continue;
+        }
+
+        let (sensor, period) = subscriptions[index];
+        let timestamp = Instant::now();
+        let reading = read(sensors, sensor);
+
+        // skip missed periods instead of bursting to catch up
+        due[index] = (next + period).max(timestamp);
+
+        let sample = Sample {
+            sensor,
+            sequence,
+            timestamp,
+            overruns: overruns.load(Ordering::Relaxed),
+            reading,
+        };
+        sequence += 1;
+
+        match deliver(sample) {
+            Delivery::Sent => {}
+            Delivery::Full => {
+                overruns.fetch_add(1, Ordering::Relaxed);
+            }
+            Delivery::Closed => return,
+        }
+    }
+}
+
+fn read(sensors: &Sensors, sensor: Sensor) -> Result<Reading, Error> {
+    Ok(match sensor {
+        Sensor::Uv => Reading::Uv(sensors.try_read_uv()?),
+        Sensor::Pressure => Reading::Pressure(sensors.try_read_pressure()?),
+        Sensor::Humidity => Reading::Humidity(sensors.try_read_humidity()?),
+        Sensor::Imu => Reading::Imu(sensors.try_read_imu()?),
+    })
+}
@@ -0,0 +1,179 @@
+//! Physical units of sensor values.
+//!
+//! Each quantity is a newtype over `f32` holding the value in the unit it is named after. The inner value is public
+//! and conversions to other common units are available as methods.
+//!
+//! ```
+//! use matrix_rhal::sensors::units::{Celsius, Pascals};
+//!
+//! let pressure = Pascals(101_325.0);
+//! assert_eq!(pressure.hectopascals(), 1013.25);
+//! assert_eq!(Celsius(100.0).fahrenheit(), 212.0);
+//! ```
+use std::fmt;
+
+/// The MCU reports most values as fixed-point integers, scaled by 1000.
+pub(crate) fn from_fixed_point(raw: i32) -> f32 {
+    raw as f32 / 1000.0
+}
+
+macro_rules! quantity {
+    ($(#[$meta:meta])* $name:ident, $symbol:expr) => {
+        $(#[$meta])*
+        #[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+        pub struct $name(pub f32);
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt::Display::fmt(&self.0, f)?;
+                write!(f, " {}", $symbol)
+            }
+        }
+
+        impl From<$name> for f32 {
+            fn from(value: $name) -> f32 {
+                value.0
+            }
+        }
+    };
+}
+
+quantity!(
+    /// Pressure, in Pascals.
+    Pascals,
+    "Pa"
+);
+
+impl Pascals {
+    /// Standard atmospheric pressure at sea level.
+    pub const SEA_LEVEL: Pascals = Pascals(101_325.0);
+
+    pub fn from_hectopascals(hectopascals: f32) -> Pascals {
+        Pascals(hectopascals * 100.0)
+    }
+
+    /// Same as millibars.
+    pub fn hectopascals(self) -> f32 {
+        self.0 / 100.0
+    }
+
+    pub fn kilopascals(self) -> f32 {
+        self.0 / 1000.0
+    }
+
+    pub fn inches_of_mercury(self) -> f32 {
+        self.0 / 3386.389
+    }
+}
+
+quantity!(
+    /// Distance, in meters.
+    Meters,
+    "m"
+);
+
+impl Meters {
+    pub fn from_feet(feet: f32) -> Meters {
+        Meters(feet * 0.3048)
+    }
+
+    pub fn feet(self) -> f32 {
+        self.0 / 0.3048
+    }
+}
+
+quantity!(
+    /// Temperature, in degrees Celsius.
+    Celsius,
+    "\u{b0}C"
+);
+
+impl Celsius {
+    pub fn from_fahrenheit(fahrenheit: f32) -> Celsius {
+        Celsius((fahrenheit - 32.0) * 5.0 / 9.0)
+    }
+
+    pub fn fahrenheit(self) -> f32 {
+        self.0 * 9.0 / 5.0 + 32.0
+    }
+
+    pub fn kelvin(self) -> f32 {
+        self.0 + 273.15
+    }
+}
+
+quantity!(
+    /// Relative humidity, in percent.
+    Percent,
+    "%"
+);
+
+impl Percent {
+    /// The value from `0.0` to `1.0`.
+    pub fn fraction(self) -> f32 {
+        self.0 / 100.0
+    }
+}
+
+quantity!(
+    /// Acceleration, in multiples of standard gravity (g).
+    GForce,
+    "g"
+);
+
+impl GForce {
+    /// Standard gravity, in m/s².
+    pub const STANDARD_GRAVITY: f32 = 9.80665;
+
+    pub fn meters_per_second_squared(self) -> f32 {
+        self.0 * GForce::STANDARD_GRAVITY
+    }
+}
+
+quantity!(
+    /// Angular velocity, in degrees per second.
+    DegreesPerSecond,
+    "\u{b0}/s"
+);
+
+impl DegreesPerSecond {
+    pub fn radians_per_second(self) -> f32 {
+        self.0.to_radians()
+    }
+}
+
+quantity!(
+    /// Magnetic flux density, in gauss.
+    Gauss,
+    "G"
+);
+
+impl Gauss {
+    pub fn microteslas(self) -> f32 {
+        self.0 * 100.0
+    }
+}
+
+quantity!(
+    /// Angle, in degrees.
+    Degrees,
+    "\u{b0}"
+);
+
+impl Degrees {
+    pub fn radians(self) -> f32 {
+        self.0.to_radians()
+    }
+}
+
+quantity!(
+    /// Density, in kilograms per cubic meter.
+    KilogramsPerCubicMeter,
+    "kg/m\u{b3}"
+);
+
+impl KilogramsPerCubicMeter {
+    pub fn grams_per_cubic_meter(self) -> f32 {
+        self.0 * 1000.0
+    }
+}
@@ -0,0 +1,270 @@
+//! Async access to the MATRIX device.
+//!
+//! Every bus transfer is a blocking ioctl, so a `BusWorker` runs them on a dedicated thread and hands the results
+//! back through futures. The futures do not depend on any particular runtime and can be awaited from tokio,
+//! async-std or a plain executor alike.
+use crate::everloop::Rgbw;
+use crate::gpio::PinConfig;
+use crate::sensors::{Humidity, Imu, Pressure, SensorSettings};
+use crate::{Bus, Device, Error, Everloop, Gpio, Sensors};
+use futures::channel::oneshot;
+use std::future::Future;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+pub mod stream;
+pub use stream::*;
+
+/// Everything the worker thread owns. GPIO and sensor state lives here so it is kept between requests.
+struct Devices<'a> {
+    sensors: Option<Sensors<'a>>,
+    gpio: Gpio<'a>,
+    everloop: Everloop<'a>,
+}
+
+type Job = Box<dyn FnOnce(&Devices) + Send>;
+
+/// Runs sensor, GPIO and Everloop requests on a background thread so that async code never blocks on the bus.
+///
+/// Requests are handled one at a time, in the order they are made. A request is queued as soon as its method is
+/// called, not when the returned future is first polled, so dropping the future doesn't cancel it: the bus
+/// operation still happens and only its result is discarded.
+///
+/// # Example
+/// ```no_run
+/// use matrix_rhal::gpio::config::State;
+/// use matrix_rhal::worker::BusWorker;
+/// use matrix_rhal::Rgbw;
+/// use std::sync::Arc;
+///
+/// async fn blink(worker: &BusWorker) -> Result<(), matrix_rhal::Error> {
+///     worker.set_all(Rgbw::new(0, 0, 255, 0)).await?;
+///     worker.set_config(0, State::On).await?;
+///
+///     let imu = worker.read_imu().await?;
+///     println!("yaw: {}", imu.yaw);
+///     Ok(())
+/// }
+///
+/// let worker = BusWorker::new(Arc::new(matrix_rhal::Bus::init().unwrap()));
+/// ```
+#[derive(Debug)]
+pub struct BusWorker {
+    bus: Arc<Bus>,
+    jobs: Mutex<Option<Sender<Job>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl BusWorker {
+    /// Start the worker thread. Sensor readings use the settings saved for the board, the same way `Sensors::new`
+    /// does.
+    pub fn new(bus: Arc<Bus>) -> BusWorker {
+        let settings = SensorSettings::load_or_default(&bus);
+        BusWorker::with_settings(bus, settings)
+    }
+
+    /// Same as `new`, but sensor readings use the given settings, such as the ones of an existing `Sensors`
+    /// instance. The settings are ignored on devices without sensors.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use matrix_rhal::worker::BusWorker;
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// let bus = Arc::new(matrix_rhal::Bus::init().unwrap());
+    /// let sensors = matrix_rhal::Sensors::new(&bus);
+    /// sensors.set_stale_timeout(Some(Duration::from_secs(5))).unwrap();
+    ///
+    /// let worker = BusWorker::with_settings(Arc::clone(&bus), sensors.settings().unwrap());
+    /// ```
+    pub fn with_settings(bus: Arc<Bus>, settings: SensorSettings) -> BusWorker {
+        let (sender, receiver) = mpsc::channel::<Job>();
+
+        let thread = {
+            let bus = Arc::clone(&bus);
+            thread::spawn(move || {
+                let devices = Devices {
+                    sensors: match bus.device_name {
+                        Device::Creator => Some(Sensors::with_settings(&bus, settings)),
+                        _ => None,
+                    },
+                    gpio: Gpio::new(&bus),
+                    everloop: Everloop::new(&bus),
+                };
+
+                for job in receiver {
+                    job(&devices);
+                }
+            })
+        };
+
+        BusWorker {
+            bus,
+            jobs: Mutex::new(Some(sender)),
+            thread: Some(thread),
+        }
+    }
+
+    /// The bus the worker is using.
+    pub fn bus(&self) -> &Arc<Bus> {
+        &self.bus
+    }
+
+    /// Queue `job` on the worker thread and return a future of its result.
+    ///
+    /// The job is sent right away, so it runs even if the future is dropped without being polled.
+    fn call<T, F>(&self, job: F) -> impl Future<Output = Result<T, Error>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Devices) -> Result<T, Error> + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        let queued = match self.jobs.lock() {
+            Ok(jobs) => jobs.as_ref().is_some_and(|jobs| {
+                jobs.send(Box::new(move |devices: &Devices| {
+                    let _ = sender.send(job(devices));
+                }))
+                .is_ok()
+            }),
+            Err(_) => false,
+        };
+
+        async move {
+            if !queued {
+                return Err(Error::BusWorkerStopped);
+            }
+
+            // the sender is dropped without a result if the job panicked
+            receiver.await.map_err(|_| Error::BusWorkerStopped)?
+        }
+    }
+
+    fn sensors<'d, 'a>(devices: &'d Devices<'a>) -> Result<&'d Sensors<'a>, Error> {
+        devices.sensors.as_ref().ok_or(Error::UnsupportedDevice)
+    }
+}
+
+///////////////////////////////
+// Sensors
+//////////////////////////////
+impl BusWorker {
+    /// Async version of `Sensors::try_read_uv`.
+    pub fn read_uv(&self) -> impl Future<Output = Result<f32, Error>> {
+        self.call(|devices| BusWorker::sensors(devices)?.try_read_uv())
+    }
+
+    /// Async version of `Sensors::try_read_pressure`.
+    pub fn read_pressure(&self) -> impl Future<Output = Result<Pressure, Error>> {
+        self.call(|devices| BusWorker::sensors(devices)?.try_read_pressure())
+    }
+
+    /// Async version of `Sensors::try_read_humidity`.
+    pub fn read_humidity(&self) -> impl Future<Output = Result<Humidity, Error>> {
+        self.call(|devices| BusWorker::sensors(devices)?.try_read_humidity())
+    }
+
+    /// Async version of `Sensors::try_read_imu`.
+    pub fn read_imu(&self) -> impl Future<Output = Result<Imu, Error>> {
+        self.call(|devices| BusWorker::sensors(devices)?.try_read_imu())
+    }
+}
+
+///////////////////////////////
+// GPIO
+//////////////////////////////
+impl BusWorker {
+    /// Async version of `Gpio::get_state`.
+    pub fn get_state(&self, pin: u8) -> impl Future<Output = Result<bool, Error>> {
+        self.call(move |devices| {
+            Gpio::is_pin_valid(pin)?;
+            Ok(devices.gpio.get_state(pin))
+        })
+    }
+
+    /// Async version of `Gpio::get_states`.
+    pub fn get_states(&self) -> impl Future<Output = Result<[bool; 16], Error>> {
+        self.call(|devices| Ok(devices.gpio.get_states()))
+    }
+
+    /// Async version of `Gpio::set_config`.
+    pub fn set_config<T>(&self, pin: u8, config: T) -> impl Future<Output = Result<(), Error>>
+    where
+        T: PinConfig + Send + 'static,
+    {
+        self.call(move |devices| devices.gpio.set_config(pin, config))
+    }
+
+    /// Async version of `Gpio::set_configs`.
+    pub fn set_configs<T>(&self, pins: &[u8], config: T) -> impl Future<Output = Result<(), Error>>
+    where
+        T: PinConfig + Send + 'static,
+    {
+        let pins = pins.to_vec();
+        self.call(move |devices| devices.gpio.set_configs(&pins, config))
+    }
+
+    /// Async version of `Gpio::set_pwm`.
+    pub fn set_pwm(
+        &self,
+        pin: u8,
+        frequency: f32,
+        percentage: f32,
+    ) -> impl Future<Output = Result<(), Error>> {
+        self.call(move |devices| devices.gpio.set_pwm(pin, frequency, percentage))
+    }
+
+    /// Async version of `Gpio::set_servo_angle`.
+    pub fn set_servo_angle(
+        &self,
+        pin: u8,
+        angle: u32,
+        min_pulse_ms: f32,
+    ) -> impl Future<Output = Result<(), Error>> {
+        self.call(move |devices| devices.gpio.set_servo_angle(pin, angle, min_pulse_ms))
+    }
+}
+
+///////////////////////////////
+// Everloop
+//////////////////////////////
+impl BusWorker {
+    /// Async version of `Everloop::try_set`.
+    pub fn set(&self, leds: &[Rgbw]) -> impl Future<Output = Result<(), Error>> {
+        let leds = leds.to_vec();
+        self.call(move |devices| devices.everloop.try_set(&leds))
+    }
+
+    /// Async version of `Everloop::set_range`.
+    pub fn set_range(
+        &self,
+        offset: usize,
+        leds: &[Rgbw],
+    ) -> impl Future<Output = Result<(), Error>> {
+        let leds = leds.to_vec();
+        self.call(move |devices| devices.everloop.set_range(offset, &leds))
+    }
+
+    /// Async version of `Everloop::try_get`.
+    pub fn get(&self) -> impl Future<Output = Result<Vec<Rgbw>, Error>> {
+        self.call(|devices| devices.everloop.try_get())
+    }
+
+    /// Async version of `Everloop::try_set_all`.
+    pub fn set_all(&self, color: Rgbw) -> impl Future<Output = Result<(), Error>> {
+        self.call(move |devices| devices.everloop.try_set_all(color))
+    }
+}
+
+impl Drop for BusWorker {
+    fn drop(&mut self) {
+        // closing the queue ends the worker once pending requests are done
+        if let Ok(mut jobs) = self.jobs.lock() {
+            jobs.take();
+        }
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
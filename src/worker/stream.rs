@@ -0,0 +1,84 @@
+use crate::sensors::stream::{Delivery, Poller, Sample, Sensor};
+use crate::sensors::SensorSettings;
+use crate::Bus;
+use futures::channel::mpsc::{self, Receiver};
+use futures::stream::Stream;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// Async version of `SensorStream`, implementing `futures::Stream`.
+///
+/// Sensors are polled on their own thread rather than through a `BusWorker`, so slow consumers never delay other
+/// requests.
+///
+/// # Example
+/// ```no_run
+/// use futures::StreamExt;
+/// use matrix_rhal::sensors::stream::Sensor;
+/// use matrix_rhal::worker::AsyncSensorStream;
+/// use std::sync::Arc;
+///
+/// async fn log_imu(bus: Arc<matrix_rhal::Bus>) {
+///     let mut stream = AsyncSensorStream::new(bus, &[(Sensor::Imu, 50.0)], 64);
+///
+///     while let Some(sample) = stream.next().await {
+///         println!("{:?}", sample.reading);
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct AsyncSensorStream {
+    receiver: Receiver<Sample>,
+    poller: Poller,
+}
+
+impl AsyncSensorStream {
+    /// Start polling each `(sensor, rate)` pair, with rates in Hz between `MIN_RATE` and `MAX_RATE`. About
+    /// `capacity` samples are queued for the consumer before new ones are dropped.
+    ///
+    /// Readings use the settings saved for the board, the same way `Sensors::new` does.
+    ///
+    /// # Panics
+    /// If the device has no sensors or a rate is not a positive number, the same way `SensorStream::new` does.
+    pub fn new(
+        bus: Arc<Bus>,
+        subscriptions: &[(Sensor, f32)],
+        capacity: usize,
+    ) -> AsyncSensorStream {
+        let settings = SensorSettings::load_or_default(&bus);
+        AsyncSensorStream::with_settings(bus, subscriptions, capacity, settings)
+    }
+
+    /// Same as `new`, but readings use the given settings, such as the ones of an existing `Sensors` instance.
+    pub fn with_settings(
+        bus: Arc<Bus>,
+        subscriptions: &[(Sensor, f32)],
+        capacity: usize,
+        settings: SensorSettings,
+    ) -> AsyncSensorStream {
+        let (mut sender, receiver) = mpsc::channel(capacity);
+        let poller = Poller::spawn(bus, subscriptions, settings, move |sample| {
+            match sender.try_send(sample) {
+                Ok(()) => Delivery::Sent,
+                Err(error) if error.is_full() => Delivery::Full,
+                Err(_) => Delivery::Closed,
+            }
+        });
+
+        AsyncSensorStream { receiver, poller }
+    }
+
+    /// Total number of samples dropped so far because the consumer fell behind.
+    pub fn overruns(&self) -> u64 {
+        self.poller.overruns()
+    }
+}
+
+impl Stream for AsyncSensorStream {
+    type Item = Sample;
+
+    fn poll_next(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Option<Sample>> {
+        Pin::new(&mut self.receiver).poll_next(context)
+    }
+}